@@ -5,12 +5,7 @@ pub fn print_project_scope(_project_description: &str) {
     /// Input: Takes in a user request to build a website project description
     /// Function: Converts user request into JSON response of information items required for a website build.
     /// Important: At least one of the bool results must be true
-    /// Output: Prints an object response in the following format:
-    ///   {
-    ///     "is_crud_required": bool, // true if site needs CRUD functionally
-    ///     "is_user_login_and_logout": bool, // true if site needs users to be able to log in and log out
-    ///     "is_external_urls_required": bool // true if site needs to fetch data from third part providers
-    ///   }
+    /// Output: Prints an object response matching the JSON Schema given with the request
     /// Example 1:
     ///   user_request = "I need a full stack website that accepts users and gets staock price data"
     ///   prints:
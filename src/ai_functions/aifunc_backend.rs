@@ -37,14 +37,8 @@ pub fn print_fixed_code(_broken_code_with_bugs: &str) {
 pub fn print_rest_api_endpoints(_code_input: &str) {
     /// INPUT: Takes in Rust webserver CODE_INPUT based on actix-web
     /// FUNCTION: Prints out the JSON schema for url endpoints and their respective types
-    /// LOGIC: Script analyses all code and can categorize into the following object keys:
-    ///   "route": This represents the url path of the endpoint
-    ///   "is_route_dynamic": if a route has curly braces in it such as {symbol} or {id} as an example, then this will be set to true
-    ///   "method": This represents the method being called
-    ///   "request_body": This represents the body of a post method request
-    ///   "response": This represents the output based upon the structs in the code and understanding the functions
-    /// IMPORTANT: Only prints out the JSON schema. No commentary or anything else.
-    /// MUST READ: All keys are strings. Even bool should be wrapped in double quotes as "bool"
+    /// LOGIC: Script analyses all code and prints one object per endpoint matching the JSON Schema given with the request
+    /// IMPORTANT: Only prints out the JSON. No commentary or anything else.
     /// EXAMPLE:
     /// INPUT_CODE:
     /// ...
@@ -72,7 +66,7 @@ pub fn print_rest_api_endpoints(_code_input: &str) {
     /// [
     ///   {
     ///     "route": "/item/{id}",
-    ///     "is_route_dynamic": "true",
+    ///     "is_route_dynamic": true,
     ///     "method": "get"
     ///     "request_body": "None",
     ///     "response": {
@@ -83,7 +77,7 @@ pub fn print_rest_api_endpoints(_code_input: &str) {
     ///   },
     ///   {
     ///     "route": "/item",
-    ///     "is_route_dynamic": "false",
+    ///     "is_route_dynamic": false,
     ///     "method": "post",
     ///     "request_body": {
     ///       "id": "number",
@@ -94,14 +88,14 @@ pub fn print_rest_api_endpoints(_code_input: &str) {
     ///   },
     ///   {
     ///     "route": "/item/{id}",
-    ///     "is_route_dynamic": "true",
+    ///     "is_route_dynamic": true,
     ///     "method": "delete",
     ///     "request_body": "None",
     ///     "response": "None"
     ///   },
     ///   {
     ///     "route": "/crypto",
-    ///     "is_route_dynamic": "false",
+    ///     "is_route_dynamic": false,
     ///     "method": "get",
     ///     "request_body": "None",
     ///     "response": "not_provided"
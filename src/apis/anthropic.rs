@@ -0,0 +1,448 @@
+use crate::apis::call_request::{
+    env_var, parse_retry_after, request_timeout_from_env, DEFAULT_REQUEST_TIMEOUT,
+};
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::LlmProvider;
+use crate::helpers::context_window::current_request;
+use crate::models::general::llm::{
+    APIChoice, APIMessage, APIResponse, APIUsage, ChatCompletion, Message,
+};
+use async_trait::async_trait;
+use dotenv::dotenv;
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use std::ops::Range;
+use std::time::Duration;
+
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+const DEFAULT_MODEL: &str = "claude-3-5-sonnet-latest";
+const DEFAULT_VERSION: &str = "2023-06-01";
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+// Connection settings for the Anthropic Messages API
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnthropicConfig {
+    pub base_url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    pub version: String,
+    // Used when the request does not set max_tokens - the Messages API requires one
+    pub max_tokens: u32,
+    pub timeout: Duration,
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            model: DEFAULT_MODEL.to_string(),
+            api_key: None,
+            version: DEFAULT_VERSION.to_string(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+}
+
+impl AnthropicConfig {
+    // Read ANTHROPIC_BASE_URL, ANTHROPIC_MODEL, ANTHROPIC_API_KEY, ANTHROPIC_VERSION
+    // and ANTHROPIC_MAX_TOKENS - the timeout is LLM_REQUEST_TIMEOUT_SECS as for OpenAI
+    #[allow(dead_code)]
+    pub fn from_env() -> Self {
+        dotenv().ok();
+
+        let defaults = Self::default();
+
+        Self {
+            base_url: env_var("ANTHROPIC_BASE_URL").unwrap_or(defaults.base_url),
+            model: env_var("ANTHROPIC_MODEL").unwrap_or(defaults.model),
+            api_key: env_var("ANTHROPIC_API_KEY"),
+            version: env_var("ANTHROPIC_VERSION").unwrap_or(defaults.version),
+            max_tokens: env_var("ANTHROPIC_MAX_TOKENS")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(defaults.max_tokens),
+            timeout: request_timeout_from_env(),
+        }
+    }
+
+    pub fn messages_url(&self) -> String {
+        format!("{}/messages", self.base_url.trim_end_matches('/'))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ContentBlock {
+    #[serde(rename = "type")]
+    pub block_type: String,
+    #[serde(default)]
+    pub text: String,
+}
+
+impl ContentBlock {
+    fn text(text: &str) -> Self {
+        Self {
+            block_type: "text".to_string(),
+            text: text.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct AnthropicMessage {
+    pub role: String,
+    pub content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct MessagesRequest {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    pub messages: Vec<AnthropicMessage>,
+    pub max_tokens: u32,
+    pub temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesResponse {
+    #[serde(default)]
+    pub model: Option<String>,
+    pub content: Vec<ContentBlock>,
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+    #[serde(default)]
+    pub usage: Option<MessagesUsage>,
+}
+
+// Messages of the latest request that has system messages - requests end at an assistant reply
+fn latest_request(messages: &[Message]) -> Range<usize> {
+    let mut end: usize = messages.len();
+    loop {
+        let start: usize = end - current_request(&messages[..end]).len();
+        if start == 0 || messages[start..end].iter().any(|m| m.role == "system") {
+            return start..end;
+        }
+        // Step over the assistant reply to the request before it
+        end = start - 1;
+    }
+}
+
+impl MessagesRequest {
+    // The system messages of the latest request become the system prompt when the conversation
+    // has a user turn - a repair or continuation turn after the reply keeps the instructions
+    // of the request it follows. Earlier system messages from agent memory are sent as user
+    // turns (the Messages API has no system role)
+    // Consecutive turns of the same role are merged into one message with several blocks, and
+    // a leading assistant turn left over from trimmed memory is dropped
+    pub fn from_chat_completion(chat_completion: &ChatCompletion, default_max_tokens: u32) -> Self {
+        let messages: &[Message] = &chat_completion.messages;
+        let has_user: bool = messages.iter().any(|m| m.role == "user");
+        let latest: Range<usize> = if has_user {
+            latest_request(messages)
+        } else {
+            0..0
+        };
+        let is_system_prompt =
+            |i: usize, message: &Message| latest.contains(&i) && message.role == "system";
+
+        let system: Option<String> = Some(
+            messages
+                .iter()
+                .enumerate()
+                .filter(|(i, m)| is_system_prompt(*i, m))
+                .map(|(_, m)| m.content.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n\n"),
+        )
+        .filter(|system| !system.is_empty());
+
+        let mut turns: Vec<AnthropicMessage> = vec![];
+        for (i, message) in messages.iter().enumerate() {
+            if is_system_prompt(i, message) {
+                continue;
+            }
+            let role: &str = match message.role.as_str() {
+                "assistant" if turns.is_empty() => continue,
+                "assistant" => "assistant",
+                _ => "user",
+            };
+
+            match turns.last_mut() {
+                Some(last) if last.role == role => {
+                    last.content.push(ContentBlock::text(&message.content))
+                }
+                _ => turns.push(AnthropicMessage {
+                    role: role.to_string(),
+                    content: vec![ContentBlock::text(&message.content)],
+                }),
+            }
+        }
+
+        Self {
+            model: chat_completion.model.clone(),
+            system,
+            messages: turns,
+            max_tokens: chat_completion.max_tokens.unwrap_or(default_max_tokens),
+            temperature: chat_completion.temperature,
+            top_p: chat_completion.top_p,
+        }
+    }
+}
+
+impl From<MessagesResponse> for APIResponse {
+    fn from(res: MessagesResponse) -> Self {
+        let content: String = res
+            .content
+            .iter()
+            .filter(|block| block.block_type == "text")
+            .map(|block| block.text.as_str())
+            .collect();
+
+        // Stop reasons in OpenAI terms so truncation is detected the same way
+        let finish_reason: Option<String> = res.stop_reason.map(|reason| {
+            match reason.as_str() {
+                "max_tokens" => "length",
+                "end_turn" | "stop_sequence" => "stop",
+                other => other,
+            }
+            .to_string()
+        });
+
+        APIResponse {
+            model: res.model,
+            choices: vec![APIChoice {
+                message: APIMessage {
+                    content,
+                    tool_calls: None,
+                },
+                finish_reason,
+            }],
+            usage: res.usage.map(|usage| APIUsage {
+                prompt_tokens: usage.input_tokens,
+                completion_tokens: usage.output_tokens,
+                total_tokens: usage.input_tokens + usage.output_tokens,
+            }),
+            structured_output: None,
+        }
+    }
+}
+
+// Anthropic Messages API client - responses are not streamed and structured output
+// relies on the prompt
+#[derive(Debug)]
+pub struct AnthropicProvider {
+    config: AnthropicConfig,
+}
+
+impl AnthropicProvider {
+    #[allow(dead_code)]
+    pub fn new(config: AnthropicConfig) -> Self {
+        Self { config }
+    }
+
+    #[allow(dead_code)]
+    pub fn from_env() -> Self {
+        Self::new(AnthropicConfig::from_env())
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        let mut headers = HeaderMap::new();
+
+        if let Some(api_key) = &self.config.api_key {
+            headers.insert(
+                "x-api-key",
+                HeaderValue::from_str(api_key).map_err(|e| LlmError::Other(e.to_string()))?,
+            );
+        }
+        headers.insert(
+            "anthropic-version",
+            HeaderValue::from_str(&self.config.version)
+                .map_err(|e| LlmError::Other(e.to_string()))?,
+        );
+
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(self.config.timeout)
+            .build()?;
+
+        let request =
+            MessagesRequest::from_chat_completion(chat_completion, self.config.max_tokens);
+        let res = client
+            .post(self.config.messages_url())
+            .json(&request)
+            .send()
+            .await?;
+
+        // Surface error statuses so they can be classified for retries (529 is overloaded)
+        let status = res.status();
+        if !status.is_success() {
+            let retry_after: Option<Duration> = parse_retry_after(res.headers());
+            let body: String = res.text().await.unwrap_or_default();
+            return Err(LlmError::Http {
+                status: status.as_u16(),
+                retry_after,
+                body,
+            });
+        }
+
+        let res: MessagesResponse = res.json().await?;
+        Ok(res.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::mock_server::spawn_mock_server;
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn tests_maps_roles_to_messages_shape() {
+        // Agent memory: earlier request and answer, then the new request
+        let chat_completion = ChatCompletion {
+            model: "claude-3-5-sonnet-latest".to_string(),
+            messages: vec![
+                message("system", "Write the code"),
+                message("assistant", "fn main() {}"),
+                message("system", "Fix the bugs"),
+            ],
+            ..Default::default()
+        };
+        let request = MessagesRequest::from_chat_completion(&chat_completion, 1024);
+        assert_eq!(request.system, None);
+        assert_eq!(request.max_tokens, 1024);
+        let roles: Vec<&str> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+
+        // Instructions with user content - separate system prompt, merged user blocks
+        let chat_completion = ChatCompletion {
+            messages: vec![
+                message("system", "You are a function pointer"),
+                message("user", "Build a todo app"),
+                message("system", "Only print JSON"),
+            ],
+            max_tokens: Some(256),
+            ..chat_completion
+        };
+        let request = MessagesRequest::from_chat_completion(&chat_completion, 1024);
+        assert_eq!(
+            request.system.as_deref(),
+            Some("You are a function pointer\n\nOnly print JSON")
+        );
+        assert_eq!(request.max_tokens, 256);
+        assert_eq!(request.messages.len(), 1);
+        assert_eq!(request.messages[0].content.len(), 1);
+
+        // Memory history - the current function's instructions are the system prompt,
+        // stale ones go with the history and the input stays in its own user turn
+        let chat_completion = ChatCompletion {
+            messages: vec![
+                message("system", "FUNCTION: print_backend_webserver_code"),
+                message("user", "<input>\nBuild a todo app\n</input>"),
+                message("assistant", "fn main() {}"),
+                message("system", "FUNCTION: print_fixed_code"),
+                message("user", "<input>\nFix the bugs\n</input>"),
+                message("assistant", "{\"broken\""),
+                message("user", "Your reply could not be decoded"),
+            ],
+            ..chat_completion
+        };
+        let request = MessagesRequest::from_chat_completion(&chat_completion, 1024);
+        assert_eq!(
+            request.system.as_deref(),
+            Some("FUNCTION: print_fixed_code")
+        );
+        let roles: Vec<&str> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(
+            roles,
+            vec!["user", "assistant", "user", "assistant", "user"]
+        );
+        assert_eq!(request.messages[2].content.len(), 1);
+
+        // Memory trimmed down to an orphaned reply - the first turn must be the user's
+        let chat_completion = ChatCompletion {
+            messages: chat_completion.messages[2..5].to_vec(),
+            ..chat_completion
+        };
+        let request = MessagesRequest::from_chat_completion(&chat_completion, 1024);
+        assert_eq!(request.messages.len(), 1);
+        assert_eq!(request.messages[0].role, "user");
+    }
+
+    #[tokio::test]
+    async fn tests_call_to_messages_endpoint() {
+        let (base_url, server) = spawn_mock_server(vec![(
+            200,
+            r#"{"id":"msg_1","type":"message","role":"assistant","content":[{"type":"text","text":"fn main() {}"}],"stop_reason":"end_turn","usage":{"input_tokens":12,"output_tokens":5}}"#
+                .to_string(),
+        )])
+        .await;
+
+        let provider = AnthropicProvider::new(AnthropicConfig {
+            base_url: format!("{}/v1", base_url),
+            api_key: Some("test-key".to_string()),
+            ..AnthropicConfig::default()
+        });
+
+        let chat_completion = ChatCompletion {
+            model: provider.model().to_string(),
+            messages: vec![
+                message("system", "You are a function pointer"),
+                message("user", "Build a todo app"),
+            ],
+            temperature: 0.1,
+            ..Default::default()
+        };
+
+        let res = provider
+            .chat_completion(&chat_completion)
+            .await
+            .expect("Failed to call messages endpoint");
+        assert_eq!(res.choices[0].message.content, "fn main() {}");
+        assert_eq!(res.usage.unwrap().total_tokens, 17);
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests[0].request_line, "POST /v1/messages HTTP/1.1");
+        assert_eq!(requests[0].header("x-api-key"), Some("test-key"));
+        assert_eq!(
+            requests[0].header("anthropic-version"),
+            Some(DEFAULT_VERSION)
+        );
+
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["system"], "You are a function pointer");
+        assert_eq!(body["max_tokens"], DEFAULT_MAX_TOKENS);
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["messages"][0]["content"][0]["type"], "text");
+        assert_eq!(
+            body["messages"][0]["content"][0]["text"],
+            "Build a todo app"
+        );
+    }
+}
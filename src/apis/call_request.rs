@@ -1,99 +1,449 @@
-use crate::models::general::llm::{APIResponse, ChatCompletion, Message};
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{LlmProvider, StructuredOutput};
+use crate::apis::streaming::read_stream;
+use crate::models::general::llm::{APIResponse, ChatCompletion, StreamOptions};
+use async_trait::async_trait;
 use dotenv::dotenv;
 use reqwest::Client;
 
 use std::env;
+use std::time::Duration;
 
 use reqwest::header::{HeaderMap, HeaderValue};
 
-// Call large Language Model (i.e. GPT-4)
-#[allow(dead_code)]
-pub async fn call_gpt(messages: Vec<Message>) -> Result<String, Box<dyn std::error::Error + Send>> {
-    dotenv().ok();
-
-    // Extract API key information
-    let api_key: String =
-        env::var("OPEN_AI_KEY").expect("OPEN_AI_KEY not found in environemtn variables");
-    let api_org: String =
-        env::var("OPEN_AI_ORG").expect("OPEN_AI_ORG not found in environment variables");
-
-    // Confirm endpoint
-    let url: &str = "https://api.openai.com/v1/chat/completions";
-
-    // Create headers
-    let mut headers = HeaderMap::new();
-
-    // Create api key header
-    headers.insert(
-        "Authorization",
-        HeaderValue::from_str(&format!("Bearer {}", api_key))
-            .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?,
-    );
-
-    // Create Open AI Org header
-    headers.insert(
-        "OpenAI-Organization",
-        HeaderValue::from_str(api_org.as_str())
-            .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?,
-    );
-
-    let clinet = Client::builder()
-        .default_headers(headers)
-        .build()
-        .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?;
-
-    // Create chat completion
-    let chat_completion: ChatCompletion = ChatCompletion {
-        model: "gpt-3.5-turbo".to_string(),
-        messages,
-        temperature: 0.1,
-    };
-
-    // Troubleshooting
-    // let res_raw = clinet
-    //     .post(url)
-    //     .json(&chat_completion)
-    //     .send()
-    //     .await
-    //     .unwrap();
-
-    // dbg!(res_raw.text().await.unwrap());
-
-    // Extract API response
-    let res: APIResponse = clinet
-        .post(url)
-        .json(&chat_completion)
-        .send()
-        .await
-        .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?
-        .json()
-        .await
-        .map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?;
-
-    // Send response
-    Ok(res.choices[0].message.content.clone())
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "gpt-3.5-turbo";
+// Whole request including a streamed body - long code replies can take minutes
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+// Connection settings for any OpenAI compatible chat completions endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenAiConfig {
+    pub base_url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    pub api_org: Option<String>,
+    pub stream: bool,
+    pub structured_output: StructuredOutput,
+    pub timeout: Duration,
+}
+
+impl Default for OpenAiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            model: DEFAULT_MODEL.to_string(),
+            api_key: None,
+            api_org: None,
+            stream: false,
+            structured_output: StructuredOutput::Tools,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+        }
+    }
+}
+
+impl OpenAiConfig {
+    // Read OPEN_AI_BASE_URL, OPEN_AI_MODEL, OPEN_AI_KEY, OPEN_AI_ORG, OPEN_AI_STREAM,
+    // OPEN_AI_STRUCTURED_OUTPUT and LLM_REQUEST_TIMEOUT_SECS
+    // Unset or empty values fall back to the OpenAI defaults / no header
+    // Other endpoints may not support tools, so they default to prompt only structured output
+    #[allow(dead_code)]
+    pub fn from_env() -> Self {
+        dotenv().ok();
+
+        let defaults = Self::default();
+        let base_url: String = env_var("OPEN_AI_BASE_URL").unwrap_or(defaults.base_url);
+        let default_structured_output = if base_url == DEFAULT_BASE_URL {
+            defaults.structured_output
+        } else {
+            StructuredOutput::Prompt
+        };
+
+        Self {
+            base_url,
+            model: env_var("OPEN_AI_MODEL").unwrap_or(defaults.model),
+            api_key: env_var("OPEN_AI_KEY"),
+            api_org: env_var("OPEN_AI_ORG"),
+            stream: env_var("OPEN_AI_STREAM").map_or(defaults.stream, |v| v == "true"),
+            structured_output: env_var("OPEN_AI_STRUCTURED_OUTPUT")
+                .and_then(|v| StructuredOutput::parse(&v))
+                .unwrap_or(default_structured_output),
+            timeout: request_timeout_from_env(),
+        }
+    }
+
+    // Full chat completions endpoint
+    pub fn completions_url(&self) -> String {
+        format!("{}/chat/completions", self.base_url.trim_end_matches('/'))
+    }
+}
+
+// LLM_REQUEST_TIMEOUT_SECS - how long a request may take before it fails with a retryable timeout
+pub fn request_timeout_from_env() -> Duration {
+    env_var("LLM_REQUEST_TIMEOUT_SECS")
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && *secs > 0.0)
+        .map_or(DEFAULT_REQUEST_TIMEOUT, Duration::from_secs_f64)
+}
+
+// Non empty environment variable
+pub fn env_var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.trim().is_empty())
+}
+
+// OpenAI chat completions client
+#[derive(Debug)]
+pub struct OpenAiProvider {
+    config: OpenAiConfig,
+}
+
+impl OpenAiProvider {
+    #[allow(dead_code)]
+    pub fn new(config: OpenAiConfig) -> Self {
+        Self { config }
+    }
+
+    // Build provider from environment variables
+    #[allow(dead_code)]
+    pub fn from_env() -> Self {
+        Self::new(OpenAiConfig::from_env())
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.config.structured_output
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        // Confirm endpoint
+        let url: String = self.config.completions_url();
+
+        // Create headers
+        let mut headers = HeaderMap::new();
+
+        // Create api key header
+        if let Some(api_key) = &self.config.api_key {
+            headers.insert(
+                "Authorization",
+                HeaderValue::from_str(&format!("Bearer {}", api_key))
+                    .map_err(|e| LlmError::Other(e.to_string()))?,
+            );
+        }
+
+        // Create Open AI Org header
+        if let Some(api_org) = &self.config.api_org {
+            headers.insert(
+                "OpenAI-Organization",
+                HeaderValue::from_str(api_org.as_str())
+                    .map_err(|e| LlmError::Other(e.to_string()))?,
+            );
+        }
+
+        let clinet = Client::builder()
+            .default_headers(headers)
+            .timeout(self.config.timeout)
+            .build()?;
+
+        // Troubleshooting
+        // let res_raw = clinet
+        //     .post(url)
+        //     .json(chat_completion)
+        //     .send()
+        //     .await
+        //     .unwrap();
+
+        // dbg!(res_raw.text().await.unwrap());
+
+        // Ask for server sent events when streaming
+        // Tool call arguments are not assembled from stream deltas - those requests are not streamed
+        let stream: bool = self.config.stream && chat_completion.tools.is_none();
+        let mut chat_completion: ChatCompletion = chat_completion.clone();
+        if stream {
+            chat_completion.stream = Some(true);
+            chat_completion.stream_options = Some(StreamOptions {
+                include_usage: true,
+            });
+        }
+
+        // Send request
+        let res = clinet.post(&url).json(&chat_completion).send().await?;
+
+        // Surface error statuses so they can be classified for retries
+        let status = res.status();
+        if !status.is_success() {
+            let retry_after: Option<Duration> = parse_retry_after(res.headers());
+            let body: String = res.text().await.unwrap_or_default();
+            return Err(LlmError::Http {
+                status: status.as_u16(),
+                retry_after,
+                body,
+            });
+        }
+
+        // Assemble streamed tokens while showing progress
+        if stream {
+            return read_stream(res, &chat_completion.meta.agent_position).await;
+        }
+
+        // Extract API response
+        let res: APIResponse = res.json().await?;
+
+        Ok(res)
+    }
+}
+
+// Retry-After in seconds - HTTP dates are ignored
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get("retry-after")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::apis::cassette::{CassetteMode, CassetteProvider};
+    use crate::apis::mock_server::spawn_mock_server;
+    use crate::helpers::json_schema::schema_for;
+    use crate::helpers::structured_output::request_structured_output;
+    use crate::models::general::llm::Message;
+    use std::sync::Arc;
 
     #[tokio::test]
     async fn tests_call_to_openai() {
-        let message = Message {
-            role: "user".to_string(),
-            content: "Hi there, this is a test. Give me a short response.".to_string(),
+        // Serve the recorded exchange - switch to CassetteMode::Record with an API key to refresh it
+        let openai = OpenAiProvider::new(OpenAiConfig {
+            model: "gpt-3.5-turbo".to_string(),
+            ..OpenAiConfig::default()
+        });
+        let provider = CassetteProvider::new(
+            Arc::new(openai),
+            CassetteMode::Replay,
+            concat!(env!("CARGO_MANIFEST_DIR"), "/cassettes/call_to_openai.json"),
+        )
+        .unwrap();
+
+        let chat_completion = ChatCompletion {
+            model: provider.model().to_string(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: "Hi there, this is a test. Give me a short response.".to_string(),
+            }],
+            temperature: 0.1,
+            ..Default::default()
         };
 
-        let messages = vec![message];
+        let res = provider.chat_completion(&chat_completion).await.unwrap();
+        assert!(!res.choices[0].message.content.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tests_call_to_local_endpoint() {
+        let (base_url, server) = spawn_mock_server(vec![(
+            200,
+            r#"{"choices":[{"message":{"content":"Hello from a local model"}}]}"#.to_string(),
+        )])
+        .await;
+
+        let provider = OpenAiProvider::new(OpenAiConfig {
+            base_url: format!("{}/v1/", base_url),
+            model: "llama-3-8b-instruct".to_string(),
+            api_key: Some("local-key".to_string()),
+            api_org: None,
+            stream: false,
+            structured_output: StructuredOutput::Prompt,
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+        });
+
+        let chat_completion = ChatCompletion {
+            model: provider.model().to_string(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: "Hi there".to_string(),
+            }],
+            temperature: 0.1,
+            ..Default::default()
+        };
+
+        let res = provider
+            .chat_completion(&chat_completion)
+            .await
+            .expect("Failed to call local endpoint");
+        assert_eq!(res.choices[0].message.content, "Hello from a local model");
+
+        let requests = server.await.unwrap();
+        assert_eq!(
+            requests[0].request_line,
+            "POST /v1/chat/completions HTTP/1.1"
+        );
+        assert_eq!(
+            requests[0].header("authorization"),
+            Some("Bearer local-key")
+        );
+        assert_eq!(requests[0].header("openai-organization"), None);
+        assert!(requests[0]
+            .body
+            .contains("\"model\":\"llama-3-8b-instruct\""));
+    }
+
+    #[tokio::test]
+    async fn tests_hung_request_times_out() {
+        // Accepts the connection and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let _server = tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(30)).await;
+        });
+
+        let provider = OpenAiProvider::new(OpenAiConfig {
+            base_url,
+            timeout: Duration::from_millis(200),
+            ..OpenAiConfig::default()
+        });
+        let chat_completion = ChatCompletion {
+            model: provider.model().to_string(),
+            ..Default::default()
+        };
+
+        let error = provider
+            .chat_completion(&chat_completion)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, LlmError::Timeout(_)));
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn tests_error_status_is_surfaced() {
+        let (base_url, _server) = spawn_mock_server(vec![(
+            429,
+            r#"{"error":{"message":"Rate limit reached"}}"#.to_string(),
+        )])
+        .await;
 
-        let res = call_gpt(messages).await;
+        let provider = OpenAiProvider::new(OpenAiConfig {
+            base_url,
+            ..OpenAiConfig::default()
+        });
+
+        let chat_completion = ChatCompletion {
+            model: provider.model().to_string(),
+            messages: vec![],
+            temperature: 0.1,
+            ..Default::default()
+        };
+
+        let res = provider.chat_completion(&chat_completion).await;
         match res {
-            Ok(res_str) => {
-                dbg!(res_str);
-                assert!(true)
-            }
-            _ => assert!(false),
+            Err(e @ LlmError::Http { status: 429, .. }) => assert!(e.is_retryable()),
+            other => panic!("Expected rate limit error, got {:?}", other),
         }
     }
+
+    #[test]
+    fn tests_parse_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+
+        headers.insert("Retry-After", HeaderValue::from_static("2"));
+        assert_eq!(parse_retry_after(&headers), Some(Duration::from_secs(2)));
+    }
+
+    #[tokio::test]
+    async fn tests_streamed_completion() {
+        let (base_url, server) = spawn_mock_server(vec![(
+            200,
+            concat!(
+                "data: {\"choices\":[{\"delta\":{\"content\":\"Hello \"}}]}\n\n",
+                "data: {\"choices\":[{\"delta\":{\"content\":\"streamed world\"}}]}\n\n",
+                "data: [DONE]\n\n"
+            )
+            .to_string(),
+        )])
+        .await;
+
+        let provider = OpenAiProvider::new(OpenAiConfig {
+            base_url,
+            stream: true,
+            ..OpenAiConfig::default()
+        });
+
+        let chat_completion = ChatCompletion {
+            model: provider.model().to_string(),
+            messages: vec![],
+            temperature: 0.1,
+            ..Default::default()
+        };
+
+        let res = provider
+            .chat_completion(&chat_completion)
+            .await
+            .expect("Failed to read stream");
+        assert_eq!(res.choices[0].message.content, "Hello streamed world");
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].body.contains("\"stream\":true"));
+    }
+
+    #[tokio::test]
+    async fn tests_tool_call_completion() {
+        let (base_url, server) = spawn_mock_server(vec![(
+            200,
+            r#"{"choices":[{"message":{"content":null,"tool_calls":[{"id":"call_1","type":"function","function":{"name":"print_site_urls","arguments":"{\"result\":[\"https://wger.de/api/v2/\"]}"}}]}}]}"#
+                .to_string(),
+        )])
+        .await;
+
+        // Streaming is skipped for tool calls
+        let provider = OpenAiProvider::new(OpenAiConfig {
+            base_url,
+            stream: true,
+            ..OpenAiConfig::default()
+        });
+        assert_eq!(provider.structured_output(), StructuredOutput::Tools);
+
+        let mut chat_completion = ChatCompletion {
+            model: provider.model().to_string(),
+            messages: vec![],
+            temperature: 0.1,
+            ..Default::default()
+        };
+        request_structured_output(
+            &mut chat_completion,
+            StructuredOutput::Tools,
+            get_function_string!(print_site_urls),
+            &schema_for::<Vec<String>>(),
+        );
+
+        let res = provider
+            .chat_completion(&chat_completion)
+            .await
+            .expect("Failed to call tools endpoint");
+        let message = res.choices[0].message.clone();
+        assert_eq!(message.content, "");
+        assert_eq!(
+            message.tool_calls.unwrap()[0].function.arguments,
+            r#"{"result":["https://wger.de/api/v2/"]}"#
+        );
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].body.contains("\"tool_choice\""));
+        assert!(!requests[0].body.contains("\"stream\""));
+    }
 }
@@ -0,0 +1,215 @@
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{LlmProvider, StructuredOutput};
+use crate::errors::AutoGptError;
+use crate::models::general::llm::{APIResponse, ChatCompletion};
+use async_trait::async_trait;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CassetteMode {
+    // Call the wrapped provider and write every exchange to the cassette
+    Record,
+    // Serve responses from the cassette only - no network
+    Replay,
+}
+
+// One recorded request and its response
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CassetteEntry {
+    pub key: String,
+    pub request: ChatCompletion,
+    pub response: APIResponse,
+}
+
+// Record / replay wrapper around another provider
+#[derive(Debug)]
+pub struct CassetteProvider {
+    inner: Arc<dyn LlmProvider>,
+    mode: CassetteMode,
+    path: PathBuf,
+    entries: Mutex<Vec<CassetteEntry>>,
+}
+
+impl CassetteProvider {
+    // Loads any existing entries at path - a missing file starts an empty cassette
+    pub fn new(
+        inner: Arc<dyn LlmProvider>,
+        mode: CassetteMode,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self, LlmError> {
+        let path: PathBuf = path.into();
+
+        let entries: Vec<CassetteEntry> = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| LlmError::Other(format!("Invalid cassette: {}", e)))?,
+            Err(_) if mode == CassetteMode::Record => vec![],
+            Err(e) => {
+                return Err(LlmError::Other(format!(
+                    "Failed to read cassette {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        Ok(Self {
+            inner,
+            mode,
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    // Wrap provider according to LLM_CASSETTE_MODE (record | replay) and LLM_CASSETTE_PATH
+    #[allow(dead_code)]
+    pub fn wrap_from_env(
+        inner: Arc<dyn LlmProvider>,
+    ) -> Result<Arc<dyn LlmProvider>, AutoGptError> {
+        dotenv().ok();
+
+        let mode: CassetteMode = match env::var("LLM_CASSETTE_MODE").as_deref() {
+            Ok("record") => CassetteMode::Record,
+            Ok("replay") => CassetteMode::Replay,
+            _ => return Ok(inner),
+        };
+
+        let path: String =
+            env::var("LLM_CASSETTE_PATH").unwrap_or_else(|_| "cassettes/run.json".to_string());
+
+        let cassette = Self::new(inner, mode, path)
+            .map_err(|e| AutoGptError::Config(format!("Failed to load LLM cassette: {}", e)))?;
+
+        Ok(Arc::new(cassette))
+    }
+
+    fn save(&self, entries: &[CassetteEntry]) -> Result<(), LlmError> {
+        let write = || -> std::io::Result<()> {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&self.path, serde_json::to_string_pretty(entries)?)
+        };
+
+        write().map_err(|e| {
+            LlmError::Other(format!(
+                "Failed to write cassette {}: {}",
+                self.path.display(),
+                e
+            ))
+        })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CassetteProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.inner.structured_output()
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        let key: String = chat_completion.fingerprint();
+
+        match self.mode {
+            CassetteMode::Replay => self
+                .entries
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|entry| entry.key == key)
+                .map(|entry| entry.response.clone())
+                .ok_or_else(|| {
+                    LlmError::Other(format!(
+                        "No cassette entry for request {} in {}",
+                        key,
+                        self.path.display()
+                    ))
+                }),
+            CassetteMode::Record => {
+                let response: APIResponse = self.inner.chat_completion(chat_completion).await?;
+
+                let mut entries = self.entries.lock().unwrap();
+                entries.retain(|entry| entry.key != key);
+                entries.push(CassetteEntry {
+                    key,
+                    request: chat_completion.clone(),
+                    response: response.clone(),
+                });
+                self.save(&entries)?;
+
+                Ok(response)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::fake_provider::FakeProvider;
+    use crate::models::general::llm::Message;
+
+    fn chat_completion(content: &str) -> ChatCompletion {
+        ChatCompletion {
+            model: "fake-model".to_string(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: content.to_string(),
+            }],
+            temperature: 0.1,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn tests_record_then_replay() {
+        let path = env::temp_dir().join(format!("auto_gpt_cassette_{}.json", std::process::id()));
+        fs::remove_file(&path).ok();
+
+        // Record
+        let recorder = CassetteProvider::new(
+            Arc::new(FakeProvider::new(vec!["first answer", "second answer"])),
+            CassetteMode::Record,
+            &path,
+        )
+        .unwrap();
+        recorder
+            .chat_completion(&chat_completion("first"))
+            .await
+            .unwrap();
+        recorder
+            .chat_completion(&chat_completion("second"))
+            .await
+            .unwrap();
+
+        // Replay - inner provider has nothing queued so any network call would panic
+        let player = CassetteProvider::new(
+            Arc::new(FakeProvider::new(vec![])),
+            CassetteMode::Replay,
+            &path,
+        )
+        .unwrap();
+        let res = player
+            .chat_completion(&chat_completion("second"))
+            .await
+            .unwrap();
+        assert_eq!(res.choices[0].message.content, "second answer");
+
+        let missing = player.chat_completion(&chat_completion("third")).await;
+        assert!(missing.is_err());
+
+        fs::remove_file(&path).ok();
+    }
+}
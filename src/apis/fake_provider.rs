@@ -0,0 +1,125 @@
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{LlmProvider, StructuredOutput};
+use crate::models::general::llm::{APIChoice, APIMessage, APIResponse, APIUsage, ChatCompletion};
+use async_trait::async_trait;
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Canned provider for tests - replies with queued responses in order
+#[derive(Debug, Default)]
+pub struct FakeProvider {
+    responses: Mutex<VecDeque<Result<String, LlmError>>>,
+    pub requests: Mutex<Vec<ChatCompletion>>,
+    structured_output: StructuredOutput,
+    finish_reasons: Mutex<VecDeque<String>>,
+    latency: Duration,
+    in_flight: AtomicUsize,
+    // Most calls that were in progress at the same time
+    pub max_in_flight: AtomicUsize,
+}
+
+impl FakeProvider {
+    pub fn new(responses: Vec<&str>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into_iter().map(|r| Ok(r.to_string())).collect()),
+            requests: Mutex::new(vec![]),
+            structured_output: StructuredOutput::Prompt,
+            ..Default::default()
+        }
+    }
+
+    // Queue failures as well as responses
+    pub fn with_results(results: Vec<Result<String, LlmError>>) -> Self {
+        Self {
+            responses: Mutex::new(results.into_iter().collect()),
+            requests: Mutex::new(vec![]),
+            structured_output: StructuredOutput::Prompt,
+            ..Default::default()
+        }
+    }
+
+    // Advertise structured output support
+    pub fn with_structured_output(mut self, structured_output: StructuredOutput) -> Self {
+        self.structured_output = structured_output;
+        self
+    }
+
+    // finish_reason of the responses in order - stop once these run out
+    pub fn with_finish_reasons(self, finish_reasons: Vec<&str>) -> Self {
+        *self.finish_reasons.lock().unwrap() =
+            finish_reasons.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    // Take this long to answer, so calls can overlap
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FakeProvider {
+    fn model(&self) -> &str {
+        "fake-model"
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.structured_output
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        self.requests.lock().unwrap().push(chat_completion.clone());
+
+        let in_flight: usize = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        let content: String = self
+            .responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("FakeProvider ran out of responses")?;
+
+        // One token per word keeps usage numbers easy to predict
+        let prompt_tokens: u32 = chat_completion
+            .messages
+            .iter()
+            .map(|m| m.content.split_whitespace().count() as u32)
+            .sum();
+        let completion_tokens: u32 = content.split_whitespace().count() as u32;
+        let finish_reason: String = self
+            .finish_reasons
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| "stop".to_string());
+
+        Ok(APIResponse {
+            model: Some(chat_completion.model.clone()),
+            choices: vec![APIChoice {
+                message: APIMessage {
+                    content,
+                    tool_calls: None,
+                },
+                finish_reason: Some(finish_reason),
+            }],
+            usage: Some(APIUsage {
+                prompt_tokens,
+                completion_tokens,
+                total_tokens: prompt_tokens + completion_tokens,
+            }),
+            structured_output: None,
+        })
+    }
+}
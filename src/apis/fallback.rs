@@ -0,0 +1,269 @@
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{LlmProvider, StructuredOutput};
+use crate::helpers::command_line::PrintCommand;
+use crate::helpers::structured_output::reshape_structured_output;
+use crate::models::general::llm::{APIResponse, ChatCompletion};
+use crate::models::general::llm_config::{LlmConfig, LlmSettings};
+use async_trait::async_trait;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Tries the fallback models configured for the calling agent and ai function
+// when the requested model is unavailable - auth and bad request errors are returned as they are
+#[derive(Debug)]
+pub struct FallbackProvider {
+    primary: Arc<dyn LlmProvider>,
+    endpoints: HashMap<String, Arc<dyn LlmProvider>>,
+    llm_config: LlmConfig,
+}
+
+impl FallbackProvider {
+    pub fn new(
+        primary: Arc<dyn LlmProvider>,
+        endpoints: HashMap<String, Arc<dyn LlmProvider>>,
+        llm_config: LlmConfig,
+    ) -> Self {
+        Self {
+            primary,
+            endpoints,
+            llm_config,
+        }
+    }
+
+    // Models to try in order - the requested one first
+    fn candidates(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<Vec<(&dyn LlmProvider, String)>, LlmError> {
+        let settings: LlmSettings = self.llm_config.resolve(
+            &chat_completion.meta.agent_position,
+            &chat_completion.meta.ai_function,
+        );
+
+        let mut candidates: Vec<(&dyn LlmProvider, String)> =
+            vec![(self.primary.as_ref(), chat_completion.model.clone())];
+
+        for fallback in settings.fallbacks {
+            let provider: &dyn LlmProvider = match &fallback.endpoint {
+                Some(name) => self
+                    .endpoints
+                    .get(name)
+                    .ok_or_else(|| LlmError::Other(format!("Unknown LLM endpoint {}", name)))?
+                    .as_ref(),
+                None => self.primary.as_ref(),
+            };
+            candidates.push((provider, fallback.model));
+        }
+
+        Ok(candidates)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FallbackProvider {
+    fn model(&self) -> &str {
+        self.primary.model()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.primary.structured_output()
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        let candidates = self.candidates(chat_completion)?;
+        let mut last_error: Option<LlmError> = None;
+
+        for (provider, model) in candidates {
+            let mut request: ChatCompletion = ChatCompletion {
+                model: model.clone(),
+                ..chat_completion.clone()
+            };
+
+            // The request was shaped for the main provider - a fallback with other
+            // structured output support is asked in its own way
+            let structured_output: StructuredOutput = provider.structured_output();
+            if structured_output != self.primary.structured_output() {
+                reshape_structured_output(
+                    &mut request,
+                    self.primary.structured_output(),
+                    structured_output,
+                );
+            }
+
+            if let Some(e) = &last_error {
+                PrintCommand::Issue.print_agent_message(
+                    &chat_completion.meta.agent_position,
+                    &format!("{}, falling back to {}", e, model),
+                );
+            }
+
+            match provider.chat_completion(&request).await {
+                Ok(mut res) => {
+                    res.model.get_or_insert(model);
+                    res.structured_output.get_or_insert(structured_output);
+                    return Ok(res);
+                }
+                Err(e) if e.warrants_fallback() => last_error = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| LlmError::Other("No model to call".to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai_functions::aifunc_architect::print_site_urls;
+    use crate::apis::fake_provider::FakeProvider;
+    use crate::helpers::general::ai_task_request_decoded;
+    use crate::models::general::llm::RequestMeta;
+
+    #[tokio::test]
+    async fn tests_falls_back_in_order() {
+        let primary = Arc::new(FakeProvider::with_results(vec![
+            Err(LlmError::Http {
+                status: 503,
+                retry_after: None,
+                body: "The server is overloaded".to_string(),
+            }),
+            Err(LlmError::Timeout("no reply".to_string())),
+        ]));
+        let local = Arc::new(FakeProvider::new(vec!["fn main() {}"]));
+
+        let llm_config: LlmConfig = serde_json::from_str(
+            r#"{
+                "agents": { "Backend Developer": { "fallbacks": [
+                    { "model": "gpt-3.5-turbo" },
+                    { "model": "llama3", "endpoint": "local" }
+                ] } },
+                "endpoints": { "local": { "kind": "openai", "base_url": "http://localhost:11434/v1" } }
+            }"#,
+        )
+        .unwrap();
+
+        let provider = FallbackProvider::new(
+            primary.clone(),
+            HashMap::from([("local".to_string(), local.clone() as Arc<dyn LlmProvider>)]),
+            llm_config.clone(),
+        );
+
+        let request = ChatCompletion {
+            model: "gpt-4".to_string(),
+            meta: RequestMeta {
+                agent_position: "Backend Developer".to_string(),
+                ai_function: "print_backend_webserver_code".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let res = provider.chat_completion(&request).await.unwrap();
+
+        assert_eq!(res.model.as_deref(), Some("llama3"));
+        assert_eq!(res.choices[0].message.content, "fn main() {}");
+
+        let tried: Vec<String> = primary
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.model.clone())
+            .collect();
+        assert_eq!(tried, vec!["gpt-4", "gpt-3.5-turbo"]);
+
+        // A rejected key fails the same way on any model - no fallback is tried
+        let primary = Arc::new(FakeProvider::with_results(vec![Err(LlmError::Http {
+            status: 401,
+            retry_after: None,
+            body: "Invalid API key".to_string(),
+        })]));
+        let provider = FallbackProvider::new(
+            primary.clone(),
+            HashMap::from([("local".to_string(), local.clone() as Arc<dyn LlmProvider>)]),
+            llm_config,
+        );
+        let error = provider.chat_completion(&request).await.unwrap_err();
+        assert!(matches!(error, LlmError::Http { status: 401, .. }));
+        assert_eq!(primary.requests.lock().unwrap().len(), 1);
+        assert_eq!(local.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn tests_falls_back_when_prompt_exceeds_context_window() {
+        let primary = Arc::new(FakeProvider::with_results(vec![
+            Err(LlmError::Http {
+                status: 400,
+                retry_after: None,
+                body: r#"{"error": {"message": "This model's maximum context length is 8192 tokens", "code": "context_length_exceeded"}}"#.to_string(),
+            }),
+            Ok("fn main() {}".to_string()),
+        ]));
+
+        let llm_config: LlmConfig = serde_json::from_str(
+            r#"{ "default": { "fallbacks": [ { "model": "gpt-4-turbo" } ] } }"#,
+        )
+        .unwrap();
+        let provider = FallbackProvider::new(primary.clone(), HashMap::new(), llm_config);
+
+        let request = ChatCompletion {
+            model: "gpt-4".to_string(),
+            ..Default::default()
+        };
+        let res = provider.chat_completion(&request).await.unwrap();
+
+        assert_eq!(res.model.as_deref(), Some("gpt-4-turbo"));
+        assert_eq!(res.choices[0].message.content, "fn main() {}");
+    }
+
+    #[tokio::test]
+    async fn tests_fallback_asked_and_decoded_in_its_own_format() {
+        let primary = Arc::new(
+            FakeProvider::with_results(vec![Err(LlmError::Timeout("no reply".to_string()))])
+                .with_structured_output(StructuredOutput::Tools),
+        );
+        // Prompt mode - answers with the bare array rather than {"result": [...]}
+        let local = Arc::new(FakeProvider::new(vec![
+            r#"["https://api.binance.com/api/v3/exchangeInfo"]"#,
+        ]));
+
+        let llm_config: LlmConfig = serde_json::from_str(
+            r#"{
+                "default": { "fallbacks": [ { "model": "llama3", "endpoint": "local" } ] },
+                "endpoints": { "local": { "kind": "openai", "base_url": "http://localhost:11434/v1" } }
+            }"#,
+        )
+        .unwrap();
+        let provider = FallbackProvider::new(
+            primary.clone(),
+            HashMap::from([("local".to_string(), local.clone() as Arc<dyn LlmProvider>)]),
+            llm_config.clone(),
+        );
+
+        let urls: Vec<String> = ai_task_request_decoded(
+            &provider,
+            &llm_config,
+            "Provides Crypto Price Data from Binance".to_string(),
+            "Solutions Architect",
+            print_site_urls,
+        )
+        .await
+        .unwrap();
+        assert_eq!(urls, vec!["https://api.binance.com/api/v3/exchangeInfo"]);
+
+        // The primary got a tool, the fallback the schema in its prompt instead
+        assert!(primary.requests.lock().unwrap()[0].tools.is_some());
+        let request = &local.requests.lock().unwrap()[0];
+        assert!(request.tools.is_none() && request.tool_choice.is_none());
+        assert!(request
+            .messages
+            .last()
+            .unwrap()
+            .content
+            .starts_with("Respond only with JSON matching this JSON Schema"));
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+use std::time::Duration;
+
+// Failure talking to a Large Language Model provider
+#[derive(Debug)]
+pub enum LlmError {
+    // Provider answered with a non success status
+    Http {
+        status: u16,
+        retry_after: Option<Duration>,
+        body: String,
+    },
+    // Request did not complete in time
+    Timeout(String),
+    // Connection could not be made or was dropped
+    Transport(String),
+    // Response body was not the expected shape
+    Decode(String),
+    // Anything else - bad headers, missing cassette entries
+    Other(String),
+    // Retry policy gave up - holds the last failure
+    RetriesExhausted {
+        attempts: u32,
+        last: Box<LlmError>,
+    },
+}
+
+impl LlmError {
+    // Rate limits, server errors, timeouts and dropped connections are worth another try
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Http { status, .. } => *status == 408 || *status == 429 || *status >= 500,
+            Self::Timeout(_) | Self::Transport(_) => true,
+            Self::Decode(_) | Self::Other(_) | Self::RetriesExhausted { .. } => false,
+        }
+    }
+
+    // Worth trying the next fallback model - the provider is unavailable or overloaded,
+    // the model does not exist there or the prompt is over its context window
+    // Auth and other bad request errors would fail the same way on every model
+    pub fn warrants_fallback(&self) -> bool {
+        match self {
+            Self::RetriesExhausted { last, .. } => last.warrants_fallback(),
+            Self::Http {
+                status: 401 | 403, ..
+            } => false,
+            Self::Http {
+                status: 404 | 413, ..
+            } => true,
+            Self::Http {
+                status: 400, body, ..
+            } => is_context_length_error(body),
+            other => other.is_retryable(),
+        }
+    }
+
+    // Delay requested by the provider via Retry-After
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Http { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+// OpenAI reports context_length_exceeded, Anthropic "prompt is too long"
+fn is_context_length_error(body: &str) -> bool {
+    let body: String = body.to_lowercase();
+    [
+        "context_length_exceeded",
+        "maximum context length",
+        "prompt is too long",
+    ]
+    .iter()
+    .any(|pattern| body.contains(pattern))
+}
+
+impl fmt::Display for LlmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http { status, body, .. } => write!(f, "provider returned {}: {}", status, body),
+            Self::Timeout(e) => write!(f, "request timed out: {}", e),
+            Self::Transport(e) => write!(f, "transport error: {}", e),
+            Self::Decode(e) => write!(f, "failed to decode provider response: {}", e),
+            Self::Other(e) => write!(f, "{}", e),
+            Self::RetriesExhausted { attempts, last } => {
+                write!(f, "gave up after {} attempts: {}", attempts, last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LlmError {}
+
+impl From<reqwest::Error> for LlmError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout(e.to_string())
+        } else if e.is_decode() {
+            Self::Decode(e.to_string())
+        } else {
+            Self::Transport(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_error_classification() {
+        let http = |status: u16| LlmError::Http {
+            status,
+            retry_after: None,
+            body: String::new(),
+        };
+
+        assert!(http(429).is_retryable());
+        assert!(http(503).is_retryable());
+        assert!(!http(400).is_retryable());
+        assert!(!http(401).is_retryable());
+        assert!(LlmError::Timeout("slow".to_string()).is_retryable());
+        assert!(!LlmError::Decode("bad json".to_string()).is_retryable());
+
+        // Only availability problems move on to a fallback model
+        let exhausted = |last: LlmError| LlmError::RetriesExhausted {
+            attempts: 3,
+            last: Box::new(last),
+        };
+        assert!(exhausted(http(503)).warrants_fallback());
+        assert!(LlmError::Transport("reset".to_string()).warrants_fallback());
+        assert!(!http(401).warrants_fallback());
+        assert!(!exhausted(http(400)).warrants_fallback());
+        assert!(http(404).warrants_fallback());
+        assert!(!http(403).warrants_fallback());
+        let context_length = LlmError::Http {
+            status: 400,
+            retry_after: None,
+            body: r#"{"error": {"code": "context_length_exceeded"}}"#.to_string(),
+        };
+        assert!(context_length.warrants_fallback());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::apis::anthropic::{AnthropicConfig, AnthropicProvider};
+use crate::apis::call_request::{env_var, request_timeout_from_env, OpenAiConfig, OpenAiProvider};
+use crate::apis::cassette::CassetteProvider;
+use crate::apis::fallback::FallbackProvider;
+use crate::apis::llm_error::LlmError;
+use crate::apis::rate_limit::{RateLimitProvider, RateLimiter};
+use crate::apis::response_cache::CacheProvider;
+use crate::apis::retry::{RetryPolicy, RetryProvider};
+use crate::errors::AutoGptError;
+use crate::models::general::llm::{APIResponse, ChatCompletion};
+use crate::models::general::llm_config::{EndpointConfig, EndpointKind, LlmConfig};
+
+use async_trait::async_trait;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// How a provider can be made to return valid JSON for decoded requests
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StructuredOutput {
+    // Rely on the prompt alone
+    #[default]
+    Prompt,
+    // response_format {"type": "json_object"}
+    JsonMode,
+    // Forced call of a function whose arguments are the output
+    Tools,
+}
+
+impl StructuredOutput {
+    // Parse prompt, json_mode or tools
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "prompt" => Some(Self::Prompt),
+            "json_mode" => Some(Self::JsonMode),
+            "tools" => Some(Self::Tools),
+            _ => None,
+        }
+    }
+}
+
+// Large Language Model provider used by helpers and agents
+#[async_trait]
+pub trait LlmProvider: Debug + Send + Sync {
+    // Model used when the caller does not ask for a specific one
+    fn model(&self) -> &str;
+
+    // Structured output support used by decoded requests
+    fn structured_output(&self) -> StructuredOutput {
+        StructuredOutput::Prompt
+    }
+
+    // Send a chat completion request and return the provider response
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError>;
+}
+
+// Provider selected by LLM_PROVIDER - openai (default, any compatible endpoint) or anthropic
+fn base_provider_from_env() -> Result<Arc<dyn LlmProvider>, AutoGptError> {
+    dotenv().ok();
+
+    match env::var("LLM_PROVIDER").unwrap_or_default().trim() {
+        "" | "openai" => Ok(Arc::new(OpenAiProvider::from_env())),
+        "anthropic" => Ok(Arc::new(AnthropicProvider::from_env())),
+        other => Err(AutoGptError::Config(format!(
+            "Unknown LLM_PROVIDER {} - expected openai or anthropic",
+            other
+        ))),
+    }
+}
+
+// Provider configured for one of the extra endpoints in the LLM config
+fn endpoint_provider(endpoint: &EndpointConfig) -> Arc<dyn LlmProvider> {
+    let api_key: Option<String> = endpoint.api_key_env.as_deref().and_then(env_var);
+
+    match endpoint.kind {
+        EndpointKind::OpenAi => {
+            let defaults = OpenAiConfig::default();
+            Arc::new(OpenAiProvider::new(OpenAiConfig {
+                base_url: endpoint.base_url.clone().unwrap_or(defaults.base_url),
+                api_key,
+                structured_output: StructuredOutput::Prompt,
+                timeout: request_timeout_from_env(),
+                ..defaults
+            }))
+        }
+        EndpointKind::Anthropic => {
+            let defaults = AnthropicConfig::default();
+            Arc::new(AnthropicProvider::new(AnthropicConfig {
+                base_url: endpoint.base_url.clone().unwrap_or(defaults.base_url),
+                api_key,
+                timeout: request_timeout_from_env(),
+                ..defaults
+            }))
+        }
+    }
+}
+
+// Provider stack configured from environment variables - see .env.example
+// Selected provider under the process wide rate limits, retried on transient failures,
+// falling back to the models configured per agent, optionally cached on disk
+// and optionally recorded or replayed
+#[allow(dead_code)]
+pub fn provider_from_env(
+    no_cache: bool,
+    llm_config: &LlmConfig,
+) -> Result<Arc<dyn LlmProvider>, AutoGptError> {
+    let provider: Arc<dyn LlmProvider> = Arc::new(RateLimitProvider::new(
+        base_provider_from_env()?,
+        RateLimiter::shared_from_env(),
+    ));
+
+    let provider: Arc<dyn LlmProvider> =
+        Arc::new(RetryProvider::new(provider, RetryPolicy::from_env()));
+
+    let endpoints: HashMap<String, Arc<dyn LlmProvider>> = llm_config
+        .endpoints
+        .iter()
+        .map(|(name, endpoint)| {
+            let provider: Arc<dyn LlmProvider> = Arc::new(RateLimitProvider::new(
+                endpoint_provider(endpoint),
+                RateLimiter::shared_from_env(),
+            ));
+            let provider: Arc<dyn LlmProvider> =
+                Arc::new(RetryProvider::new(provider, RetryPolicy::from_env()));
+            (name.clone(), provider)
+        })
+        .collect();
+
+    let provider: Arc<dyn LlmProvider> = Arc::new(FallbackProvider::new(
+        provider,
+        endpoints,
+        llm_config.clone(),
+    ));
+
+    let provider: Arc<dyn LlmProvider> = CacheProvider::wrap_from_env(provider, no_cache);
+
+    CassetteProvider::wrap_from_env(provider)
+}
@@ -1 +1,16 @@
+pub mod anthropic;
 pub mod call_request;
+pub mod cassette;
+#[cfg(test)]
+pub mod fake_provider;
+pub mod fallback;
+pub mod llm_error;
+pub mod llm_provider;
+#[cfg(test)]
+pub mod mock_server;
+pub mod rate_limit;
+pub mod response_cache;
+pub mod retry;
+pub mod streaming;
+pub mod transcript;
+pub mod usage_tracker;
@@ -0,0 +1,274 @@
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{LlmProvider, StructuredOutput};
+use crate::helpers::command_line::PrintCommand;
+use crate::models::general::llm::{APIResponse, ChatCompletion};
+use async_trait::async_trait;
+use dotenv::dotenv;
+use tokio::sync::{Mutex, Semaphore};
+
+use std::collections::VecDeque;
+use std::env;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+// Client side limits - None means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateLimits {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+    pub max_concurrency: Option<usize>,
+}
+
+impl RateLimits {
+    // Read LLM_REQUESTS_PER_MINUTE, LLM_TOKENS_PER_MINUTE and LLM_MAX_CONCURRENCY
+    #[allow(dead_code)]
+    pub fn from_env() -> Self {
+        dotenv().ok();
+
+        let parse = |key: &str| {
+            env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<u32>().ok())
+                .filter(|v| *v > 0)
+        };
+
+        Self {
+            requests_per_minute: parse("LLM_REQUESTS_PER_MINUTE"),
+            tokens_per_minute: parse("LLM_TOKENS_PER_MINUTE"),
+            max_concurrency: parse("LLM_MAX_CONCURRENCY").map(|v| v as usize),
+        }
+    }
+}
+
+// Requests and tokens sent during the last minute
+#[derive(Debug, Default)]
+struct RateWindow {
+    // (sent at, requests, tokens)
+    sent: VecDeque<(Instant, u32, u32)>,
+}
+
+impl RateWindow {
+    // How long to wait before a request of the given size fits the limits
+    // An empty window always admits - a single oversized request must not wait forever
+    fn wait_time(&mut self, limits: &RateLimits, now: Instant, tokens: u32) -> Option<Duration> {
+        while let Some((at, _, _)) = self.sent.front() {
+            if now.duration_since(*at) < WINDOW {
+                break;
+            }
+            self.sent.pop_front();
+        }
+
+        let (oldest, _, _) = *self.sent.front()?;
+        let requests: u32 = self.sent.iter().map(|(_, r, _)| r).sum();
+        let used_tokens: u32 = self.sent.iter().map(|(_, _, t)| t).sum();
+
+        let over_requests: bool = limits
+            .requests_per_minute
+            .is_some_and(|rpm| requests >= rpm);
+        let over_tokens: bool = limits
+            .tokens_per_minute
+            .is_some_and(|tpm| used_tokens.saturating_add(tokens) > tpm);
+
+        if over_requests || over_tokens {
+            Some(WINDOW.saturating_sub(now.duration_since(oldest)))
+        } else {
+            None
+        }
+    }
+
+    fn record(&mut self, now: Instant, requests: u32, tokens: u32) {
+        self.sent.push_back((now, requests, tokens));
+    }
+}
+
+// Limits shared by every provider stack in the process
+// Waiting callers are served first come first served (tokio locks and semaphores are FIFO)
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    window: Mutex<RateWindow>,
+    concurrency: Option<Semaphore>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            window: Mutex::new(RateWindow::default()),
+            concurrency: limits.max_concurrency.map(Semaphore::new),
+        }
+    }
+
+    // Process wide limiter configured from the environment on first use
+    #[allow(dead_code)]
+    pub fn shared_from_env() -> Arc<Self> {
+        static SHARED: OnceLock<Arc<RateLimiter>> = OnceLock::new();
+        SHARED
+            .get_or_init(|| Arc::new(Self::new(RateLimits::from_env())))
+            .clone()
+    }
+
+    // Queue until the request fits the per minute limits, then count it
+    async fn admit(&self, agent_position: &str, tokens: u32) {
+        let mut window = self.window.lock().await;
+
+        // Holding the lock while waiting keeps later callers queued behind this one
+        while let Some(wait) = window.wait_time(&self.limits, Instant::now(), tokens) {
+            PrintCommand::Issue.print_agent_message(
+                "LLM Provider",
+                &format!(
+                    "Rate limit reached for {}, waiting {:.1}s",
+                    agent_position,
+                    wait.as_secs_f64()
+                ),
+            );
+            tokio::time::sleep(wait).await;
+        }
+
+        window.record(Instant::now(), 1, tokens);
+    }
+
+    // Actual usage is only known afterwards - count what the estimate missed
+    async fn record_tokens(&self, tokens: u32) {
+        if tokens > 0 {
+            self.window.lock().await.record(Instant::now(), 0, tokens);
+        }
+    }
+}
+
+// Rough prompt size (about four characters per token) plus the completion budget
+fn estimate_tokens(chat_completion: &ChatCompletion) -> u32 {
+    let chars: usize = chat_completion
+        .messages
+        .iter()
+        .map(|m| m.content.len())
+        .sum();
+    (chars / 4) as u32 + chat_completion.max_tokens.unwrap_or(0)
+}
+
+// Applies a shared RateLimiter to the wrapped provider
+#[derive(Debug)]
+pub struct RateLimitProvider {
+    inner: Arc<dyn LlmProvider>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, limiter: Arc<RateLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RateLimitProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.inner.structured_output()
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        let _permit = match &self.limiter.concurrency {
+            Some(semaphore) => Some(
+                semaphore
+                    .acquire()
+                    .await
+                    .map_err(|e| LlmError::Other(e.to_string()))?,
+            ),
+            None => None,
+        };
+
+        let estimate: u32 = estimate_tokens(chat_completion);
+        self.limiter
+            .admit(&chat_completion.meta.agent_position, estimate)
+            .await;
+
+        let res: APIResponse = self.inner.chat_completion(chat_completion).await?;
+
+        let used: u32 = res.usage.map_or(0, |usage| usage.total_tokens);
+        self.limiter
+            .record_tokens(used.saturating_sub(estimate))
+            .await;
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::fake_provider::FakeProvider;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn tests_window_limits() {
+        let limits = RateLimits {
+            requests_per_minute: Some(2),
+            tokens_per_minute: Some(100),
+            max_concurrency: None,
+        };
+        let start = Instant::now();
+        let mut window = RateWindow::default();
+
+        // Oversized requests still go through on an empty window
+        assert_eq!(window.wait_time(&limits, start, 500), None);
+        window.record(start, 1, 40);
+
+        assert_eq!(
+            window.wait_time(&limits, start, 70),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(window.wait_time(&limits, start, 10), None);
+        window.record(start + Duration::from_secs(10), 1, 10);
+
+        // Request cap reached until the first request leaves the window
+        assert_eq!(
+            window.wait_time(&limits, start + Duration::from_secs(20), 1),
+            Some(Duration::from_secs(40))
+        );
+        assert_eq!(
+            window.wait_time(&limits, start + Duration::from_secs(60), 1),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn tests_concurrent_callers_queue() {
+        let limiter = Arc::new(RateLimiter::new(RateLimits {
+            max_concurrency: Some(2),
+            ..RateLimits::default()
+        }));
+        let fake = Arc::new(
+            FakeProvider::new(vec!["first", "second", "third", "fourth", "fifth"])
+                .with_latency(Duration::from_millis(20)),
+        );
+        let provider = Arc::new(RateLimitProvider::new(fake.clone(), limiter));
+
+        let calls = (0..5).map(|_| {
+            let provider = provider.clone();
+            tokio::spawn(async move {
+                provider
+                    .chat_completion(&ChatCompletion::default())
+                    .await
+                    .map(|res| res.choices[0].message.content.clone())
+            })
+        });
+
+        let mut replies: Vec<String> = vec![];
+        for call in calls.collect::<Vec<_>>() {
+            replies.push(call.await.unwrap().unwrap());
+        }
+        replies.sort();
+        assert_eq!(replies, vec!["fifth", "first", "fourth", "second", "third"]);
+
+        // Callers overlapped, but never more than max_concurrency at once
+        assert_eq!(fake.max_in_flight.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{LlmProvider, StructuredOutput};
+use crate::models::general::llm::{APIResponse, ChatCompletion};
+use async_trait::async_trait;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_CACHE_DIR: &str = ".cache/llm";
+const DEFAULT_TTL_SECS: u64 = 24 * 60 * 60;
+
+// Cached response on disk
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    created_at: u64,
+    response: APIResponse,
+}
+
+// Content addressed on-disk cache in front of another provider
+// Requests are keyed by model, temperature and messages (see ChatCompletion::fingerprint)
+#[derive(Debug)]
+pub struct CacheProvider {
+    inner: Arc<dyn LlmProvider>,
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl CacheProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, dir: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+            ttl,
+        }
+    }
+
+    // Opt in with LLM_CACHE=true - LLM_CACHE_DIR and LLM_CACHE_TTL_SECS tune it
+    // no_cache (the --no-cache flag) always bypasses the cache
+    #[allow(dead_code)]
+    pub fn wrap_from_env(inner: Arc<dyn LlmProvider>, no_cache: bool) -> Arc<dyn LlmProvider> {
+        dotenv().ok();
+
+        if no_cache || env::var("LLM_CACHE").as_deref() != Ok("true") {
+            return inner;
+        }
+
+        let dir: String =
+            env::var("LLM_CACHE_DIR").unwrap_or_else(|_| DEFAULT_CACHE_DIR.to_string());
+        let ttl_secs: u64 = env::var("LLM_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_TTL_SECS);
+
+        Arc::new(Self::new(inner, dir, Duration::from_secs(ttl_secs)))
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    // Fresh cached response - missing, unreadable or expired entries are a miss
+    fn lookup(&self, key: &str) -> Option<APIResponse> {
+        let contents: String = fs::read_to_string(self.entry_path(key)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&contents).ok()?;
+
+        let age: u64 = now_secs().saturating_sub(entry.created_at);
+        if age >= self.ttl.as_secs() {
+            return None;
+        }
+
+        Some(entry.response)
+    }
+
+    // Failing to write the cache never fails the request
+    fn store(&self, key: &str, response: &APIResponse) {
+        let entry = CacheEntry {
+            created_at: now_secs(),
+            response: response.clone(),
+        };
+
+        let written = fs::create_dir_all(&self.dir).and_then(|_| {
+            fs::write(
+                self.entry_path(key),
+                serde_json::to_string(&entry).unwrap_or_default(),
+            )
+        });
+
+        if let Err(e) = written {
+            println!("Failed to write LLM cache entry {}: {}", key, e);
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[async_trait]
+impl LlmProvider for CacheProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.inner.structured_output()
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        let key: String = chat_completion.fingerprint();
+
+        // A cache hit costs nothing - drop the original usage so it is not billed twice
+        if let Some(mut response) = self.lookup(&key) {
+            response.usage = None;
+            return Ok(response);
+        }
+
+        let response: APIResponse = self.inner.chat_completion(chat_completion).await?;
+        self.store(&key, &response);
+
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::fake_provider::FakeProvider;
+    use crate::models::general::llm::Message;
+
+    fn chat_completion(temperature: f32) -> ChatCompletion {
+        ChatCompletion {
+            model: "fake-model".to_string(),
+            messages: vec![Message {
+                role: "system".to_string(),
+                content: "print_project_scope".to_string(),
+            }],
+            temperature,
+            ..Default::default()
+        }
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("auto_gpt_{}_{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    #[tokio::test]
+    async fn tests_cache_hit_skips_provider() {
+        let dir = cache_dir("cache_hit");
+        let fake = Arc::new(FakeProvider::new(vec!["first", "second"]));
+        let provider = CacheProvider::new(fake.clone(), &dir, Duration::from_secs(60));
+
+        let first = provider
+            .chat_completion(&chat_completion(0.1))
+            .await
+            .unwrap();
+        let second = provider
+            .chat_completion(&chat_completion(0.1))
+            .await
+            .unwrap();
+        assert_eq!(first.choices, second.choices);
+        assert_eq!(second.usage, None);
+        assert_eq!(fake.requests.lock().unwrap().len(), 1);
+
+        // Different temperature is a different key
+        let third = provider
+            .chat_completion(&chat_completion(0.7))
+            .await
+            .unwrap();
+        assert_eq!(third.choices[0].message.content, "second");
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn tests_expired_entry_is_refreshed() {
+        let dir = cache_dir("cache_expired");
+        let fake = Arc::new(FakeProvider::new(vec!["stale", "fresh"]));
+        let provider = CacheProvider::new(fake.clone(), &dir, Duration::ZERO);
+
+        provider
+            .chat_completion(&chat_completion(0.1))
+            .await
+            .unwrap();
+        let res = provider
+            .chat_completion(&chat_completion(0.1))
+            .await
+            .unwrap();
+        assert_eq!(res.choices[0].message.content, "fresh");
+        assert_eq!(fake.requests.lock().unwrap().len(), 2);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,253 @@
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{LlmProvider, StructuredOutput};
+use crate::helpers::command_line::PrintCommand;
+use crate::models::general::llm::{APIResponse, ChatCompletion};
+use async_trait::async_trait;
+use dotenv::dotenv;
+
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// How often and how patiently to retry failed LLM calls
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Read LLM_MAX_ATTEMPTS, LLM_RETRY_BASE_DELAY_MS and LLM_RETRY_MAX_DELAY_MS
+    #[allow(dead_code)]
+    pub fn from_env() -> Self {
+        dotenv().ok();
+
+        let defaults = Self::default();
+        let parse = |key: &str| {
+            env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        };
+
+        Self {
+            max_attempts: parse("LLM_MAX_ATTEMPTS")
+                .map(|v| v.max(1) as u32)
+                .unwrap_or(defaults.max_attempts),
+            base_delay: parse("LLM_RETRY_BASE_DELAY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.base_delay),
+            max_delay: parse("LLM_RETRY_MAX_DELAY_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.max_delay),
+            jitter: defaults.jitter,
+        }
+    }
+
+    // Exponential backoff for the given failed attempt (1 based), capped at max_delay
+    // With jitter the delay is picked between half and all of the backoff
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp: u32 = attempt.saturating_sub(1).min(16);
+        let delay: Duration = self
+            .base_delay
+            .saturating_mul(2u32.pow(exp))
+            .min(self.max_delay);
+
+        if !self.jitter {
+            return delay;
+        }
+
+        let nanos: u32 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        delay / 2 + delay.mul_f64(f64::from(nanos % 1000) / 2000.0)
+    }
+}
+
+// Retries retryable failures of the wrapped provider according to a RetryPolicy
+#[derive(Debug)]
+pub struct RetryProvider {
+    inner: Arc<dyn LlmProvider>,
+    policy: RetryPolicy,
+}
+
+impl RetryProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RetryProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.inner.structured_output()
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        let mut attempt: u32 = 0;
+
+        loop {
+            attempt += 1;
+
+            let err: LlmError = match self.inner.chat_completion(chat_completion).await {
+                Ok(res) => return Ok(res),
+                Err(e) => e,
+            };
+
+            // Bad requests will fail the same way again
+            if !err.is_retryable() {
+                return Err(err);
+            }
+
+            if attempt >= self.policy.max_attempts {
+                return Err(LlmError::RetriesExhausted {
+                    attempts: attempt,
+                    last: Box::new(err),
+                });
+            }
+
+            // Honor Retry-After when the provider sends one, up to max_delay
+            let delay: Duration = err
+                .retry_after()
+                .map(|retry_after| retry_after.min(self.policy.max_delay))
+                .unwrap_or_else(|| self.policy.backoff(attempt));
+
+            PrintCommand::Issue.print_agent_message(
+                "LLM Provider",
+                format!(
+                    "Attempt {} failed ({}), retrying in {:?}",
+                    attempt, err, delay
+                )
+                .as_str(),
+            );
+
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::fake_provider::FakeProvider;
+    use crate::models::general::llm::Message;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            jitter: false,
+        }
+    }
+
+    fn chat_completion() -> ChatCompletion {
+        ChatCompletion {
+            model: "fake-model".to_string(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: "Hi".to_string(),
+            }],
+            temperature: 0.1,
+            ..Default::default()
+        }
+    }
+
+    fn http(status: u16) -> LlmError {
+        LlmError::Http {
+            status,
+            retry_after: None,
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn tests_backoff_is_exponential_and_capped() {
+        let policy = policy();
+        assert_eq!(policy.backoff(1), Duration::from_millis(1));
+        assert_eq!(policy.backoff(2), Duration::from_millis(2));
+        assert_eq!(policy.backoff(3), Duration::from_millis(4));
+        assert_eq!(policy.backoff(10), Duration::from_millis(5));
+    }
+
+    #[tokio::test]
+    async fn tests_retries_until_success() {
+        let fake = Arc::new(FakeProvider::with_results(vec![
+            Err(http(429)),
+            Err(LlmError::Timeout("slow".to_string())),
+            Ok("done".to_string()),
+        ]));
+        let provider = RetryProvider::new(fake.clone(), policy());
+
+        let res = provider.chat_completion(&chat_completion()).await.unwrap();
+        assert_eq!(res.choices[0].message.content, "done");
+        assert_eq!(fake.requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn tests_retry_after_is_capped() {
+        let fake = Arc::new(FakeProvider::with_results(vec![
+            Err(LlmError::Http {
+                status: 429,
+                retry_after: Some(Duration::from_secs(3600)),
+                body: String::new(),
+            }),
+            Ok("done".to_string()),
+        ]));
+        let provider = RetryProvider::new(fake, policy());
+
+        let res = tokio::time::timeout(
+            Duration::from_secs(5),
+            provider.chat_completion(&chat_completion()),
+        )
+        .await
+        .expect("Retry-After was not capped at max_delay");
+        assert_eq!(res.unwrap().choices[0].message.content, "done");
+    }
+
+    #[tokio::test]
+    async fn tests_does_not_retry_bad_request() {
+        let fake = Arc::new(FakeProvider::with_results(vec![Err(http(400))]));
+        let provider = RetryProvider::new(fake.clone(), policy());
+
+        let res = provider.chat_completion(&chat_completion()).await;
+        assert!(matches!(res, Err(LlmError::Http { status: 400, .. })));
+        assert_eq!(fake.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn tests_gives_up_after_max_attempts() {
+        let fake = Arc::new(FakeProvider::with_results(vec![
+            Err(http(500)),
+            Err(http(502)),
+            Err(http(503)),
+        ]));
+        let provider = RetryProvider::new(fake, policy());
+
+        let res = provider.chat_completion(&chat_completion()).await;
+        assert!(matches!(
+            res,
+            Err(LlmError::RetriesExhausted { attempts: 3, .. })
+        ));
+    }
+}
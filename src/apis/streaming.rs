@@ -0,0 +1,190 @@
+use crate::apis::llm_error::LlmError;
+use crate::helpers::command_line::PrintCommand;
+use crate::models::general::llm::{APIChoice, APIMessage, APIResponse, APIStreamChunk, APIUsage};
+use futures_util::StreamExt;
+use serde_json::Value;
+
+// Assembles server sent event lines of a streamed completion into a full response
+#[derive(Debug, Default)]
+pub struct StreamAccumulator {
+    buffer: Vec<u8>,
+    content: String,
+    usage: Option<APIUsage>,
+    finish_reason: Option<String>,
+    done: bool,
+}
+
+impl StreamAccumulator {
+    // Feed raw bytes as they arrive - returns the newly received text
+    pub fn push(&mut self, bytes: &[u8]) -> Result<String, LlmError> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut received: String = String::new();
+
+        // Only complete lines are parsed, the remainder waits for the next chunk
+        while let Some(line_end) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line_bytes: Vec<u8> = self.buffer.drain(..=line_end).collect();
+            let line: String = String::from_utf8_lossy(&line_bytes).trim().to_string();
+
+            let data: &str = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => continue,
+            };
+
+            if data == "[DONE]" {
+                self.done = true;
+                continue;
+            }
+
+            let event: Value =
+                serde_json::from_str(data).map_err(|e| LlmError::Decode(e.to_string()))?;
+            if let Some(error) = event.get("error") {
+                return Err(stream_error(error));
+            }
+            let chunk: APIStreamChunk =
+                serde_json::from_value(event).map_err(|e| LlmError::Decode(e.to_string()))?;
+
+            if let Some(usage) = chunk.usage {
+                self.usage = Some(usage);
+            }
+
+            for choice in chunk.choices {
+                if let Some(content) = choice.delta.content {
+                    received.push_str(&content);
+                }
+                if choice.finish_reason.is_some() {
+                    self.finish_reason = choice.finish_reason;
+                }
+            }
+        }
+
+        self.content.push_str(&received);
+        Ok(received)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    // A stream that ends without [DONE] was cut off - the content is only part of the reply
+    pub fn finish(self) -> Result<APIResponse, LlmError> {
+        if !self.done {
+            return Err(LlmError::Transport(format!(
+                "stream ended before [DONE] after {} chars",
+                self.content.chars().count()
+            )));
+        }
+
+        Ok(APIResponse {
+            model: None,
+            choices: vec![APIChoice {
+                message: APIMessage {
+                    content: self.content,
+                    tool_calls: None,
+                },
+                finish_reason: self.finish_reason,
+            }],
+            usage: self.usage,
+            structured_output: None,
+        })
+    }
+}
+
+// Error event sent in place of a chunk - server side failures are reported like a 500 status
+// so the retry policy picks them up, anything else is not worth another try
+fn stream_error(error: &Value) -> LlmError {
+    let message: String = error["message"]
+        .as_str()
+        .map_or_else(|| error.to_string(), str::to_string);
+
+    match error["type"].as_str() {
+        Some("server_error" | "api_error" | "overloaded_error") => LlmError::Http {
+            status: 500,
+            retry_after: None,
+            body: message,
+        },
+        _ => LlmError::Other(format!("provider sent an error event: {}", message)),
+    }
+}
+
+// Read a streamed response body, showing progress for the calling agent
+pub async fn read_stream(
+    res: reqwest::Response,
+    agent_position: &str,
+) -> Result<APIResponse, LlmError> {
+    let mut accumulator = StreamAccumulator::default();
+    let mut received_chars: usize = 0;
+    let mut body = res.bytes_stream();
+
+    while let Some(bytes) = body.next().await {
+        let received: String = accumulator.push(&bytes?)?;
+        received_chars += received.chars().count();
+        PrintCommand::AICall.print_stream_progress(agent_position, received_chars);
+
+        if accumulator.is_done() {
+            break;
+        }
+    }
+
+    PrintCommand::AICall.finish_stream_progress();
+
+    accumulator.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_accumulates_split_events() {
+        let mut accumulator = StreamAccumulator::default();
+
+        let first = accumulator
+            .push(b"data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\ndata: {\"choices\":[{\"delta\":{\"content\":\"fn ma")
+            .unwrap();
+        assert_eq!(first, "");
+
+        let second = accumulator
+            .push(b"in\"}}]}\n\ndata: {\"choices\":[{\"delta\":{\"content\":\"() {}\"},\"finish_reason\":\"length\"}]}\n\n")
+            .unwrap();
+        assert_eq!(second, "fn main() {}");
+
+        accumulator
+            .push(b"data: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":4,\"total_tokens\":9}}\n\ndata: [DONE]\n\n")
+            .unwrap();
+        assert!(accumulator.is_done());
+
+        let res = accumulator.finish().unwrap();
+        assert_eq!(res.choices[0].message.content, "fn main() {}");
+        assert_eq!(res.usage.unwrap().total_tokens, 9);
+        assert!(res.choices[0].is_truncated());
+    }
+
+    #[test]
+    fn tests_dropped_stream_and_error_events_fail() {
+        // Connection dropped mid reply - no [DONE]
+        let mut accumulator = StreamAccumulator::default();
+        accumulator
+            .push(b"data: {\"choices\":[{\"delta\":{\"content\":\"fn main() {\"}}]}\n\n")
+            .unwrap();
+        assert!(matches!(
+            accumulator.finish(),
+            Err(LlmError::Transport(e)) if e.contains("before [DONE]")
+        ));
+
+        let mut accumulator = StreamAccumulator::default();
+        let error = accumulator
+            .push(b"data: {\"error\":{\"message\":\"The server had an error\",\"type\":\"server_error\"}}\n\n")
+            .unwrap_err();
+        assert!(error.is_retryable());
+        assert_eq!(
+            error.to_string(),
+            "provider returned 500: The server had an error"
+        );
+
+        let error = StreamAccumulator::default()
+            .push(b"data: {\"error\":{\"message\":\"Bad request\",\"type\":\"invalid_request_error\"}}\n")
+            .unwrap_err();
+        assert!(!error.is_retryable());
+    }
+}
@@ -0,0 +1,279 @@
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{LlmProvider, StructuredOutput};
+use crate::errors::AutoGptError;
+use crate::models::general::llm::{APIMessage, APIResponse, APIUsage, ChatCompletion, Message};
+use async_trait::async_trait;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_TRANSCRIPT_DIR: &str = "transcripts";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Error,
+}
+
+// One request/response pair - a line of the transcript
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TranscriptEntry {
+    pub timestamp: u64,
+    pub agent_position: String,
+    pub ai_function: String,
+    pub model: String,
+    // Model that actually answered - differs from model when a fallback served the call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_by: Option<String>,
+    pub latency_ms: u64,
+    pub usage: Option<APIUsage>,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub request: Vec<Message>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<APIMessage>,
+}
+
+// Which entries to show - unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TranscriptFilter {
+    pub agent_position: Option<String>,
+    pub ai_function: Option<String>,
+    pub errors_only: bool,
+}
+
+impl TranscriptFilter {
+    pub fn matches(&self, entry: &TranscriptEntry) -> bool {
+        self.agent_position
+            .as_ref()
+            .is_none_or(|agent| &entry.agent_position == agent)
+            && self
+                .ai_function
+                .as_ref()
+                .is_none_or(|function| &entry.ai_function == function)
+            && (!self.errors_only || entry.outcome == Outcome::Error)
+    }
+}
+
+// Read every entry of a transcript file
+pub fn read_transcript(path: &Path) -> Result<Vec<TranscriptEntry>, AutoGptError> {
+    let io_error = |source: io::Error| AutoGptError::Io {
+        path: path.display().to_string(),
+        source,
+    };
+
+    fs::read_to_string(path)
+        .map_err(io_error)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                io_error(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", i + 1, e),
+                ))
+            })
+        })
+        .collect()
+}
+
+// Appends every call of the wrapped provider to a JSONL transcript
+#[derive(Debug)]
+pub struct TranscriptProvider {
+    inner: Arc<dyn LlmProvider>,
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl TranscriptProvider {
+    pub fn new(
+        inner: Arc<dyn LlmProvider>,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self, AutoGptError> {
+        let path: PathBuf = path.into();
+        let io_error = |source: io::Error| AutoGptError::Io {
+            path: path.display().to_string(),
+            source,
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        let file: File = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(io_error)?;
+
+        Ok(Self {
+            inner,
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    // One transcript per run in LLM_TRANSCRIPT_DIR - LLM_TRANSCRIPT=false turns it off
+    #[allow(dead_code)]
+    pub fn wrap_from_env(
+        inner: Arc<dyn LlmProvider>,
+    ) -> Result<Arc<dyn LlmProvider>, AutoGptError> {
+        dotenv().ok();
+
+        if env::var("LLM_TRANSCRIPT").as_deref() == Ok("false") {
+            return Ok(inner);
+        }
+
+        let dir: String =
+            env::var("LLM_TRANSCRIPT_DIR").unwrap_or_else(|_| DEFAULT_TRANSCRIPT_DIR.to_string());
+        let path: PathBuf =
+            Path::new(&dir).join(format!("run-{}-{}.jsonl", now_secs(), std::process::id()));
+
+        Ok(Arc::new(Self::new(inner, path)?))
+    }
+
+    // Failing to write the transcript never fails the request
+    fn append(&self, entry: &TranscriptEntry) {
+        let line: String = serde_json::to_string(entry).unwrap_or_default();
+        let mut file = self.file.lock().unwrap();
+
+        if let Err(e) = writeln!(file, "{}", line).and_then(|_| file.flush()) {
+            println!("Failed to write transcript {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[async_trait]
+impl LlmProvider for TranscriptProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.inner.structured_output()
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        let started = Instant::now();
+        let res = self.inner.chat_completion(chat_completion).await;
+
+        let (outcome, error, served_by, usage, response) = match &res {
+            Ok(res) => (
+                Outcome::Success,
+                None,
+                res.model.clone(),
+                res.usage,
+                res.choices.first().map(|choice| choice.message.clone()),
+            ),
+            Err(e) => (Outcome::Error, Some(e.to_string()), None, None, None),
+        };
+
+        self.append(&TranscriptEntry {
+            timestamp: now_secs(),
+            agent_position: chat_completion.meta.agent_position.clone(),
+            ai_function: chat_completion.meta.ai_function.clone(),
+            model: chat_completion.model.clone(),
+            served_by,
+            latency_ms: started.elapsed().as_millis() as u64,
+            usage,
+            outcome,
+            error,
+            request: chat_completion.messages.clone(),
+            response,
+        });
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::fake_provider::FakeProvider;
+    use crate::models::general::llm::RequestMeta;
+
+    fn chat_completion(agent_position: &str, ai_function: &str) -> ChatCompletion {
+        ChatCompletion {
+            model: "fake-model".to_string(),
+            messages: vec![Message {
+                role: "system".to_string(),
+                content: ai_function.to_string(),
+            }],
+            meta: RequestMeta {
+                agent_position: agent_position.to_string(),
+                ai_function: ai_function.to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn tests_transcript_records_and_filters() {
+        let path =
+            env::temp_dir().join(format!("auto_gpt_transcript_{}.jsonl", std::process::id()));
+        fs::remove_file(&path).ok();
+
+        let fake = Arc::new(FakeProvider::with_results(vec![
+            Ok("{\"is_crud_required\": true}".to_string()),
+            Err(LlmError::Timeout("no reply".to_string())),
+        ]));
+        let provider = TranscriptProvider::new(fake, &path).unwrap();
+
+        provider
+            .chat_completion(&chat_completion(
+                "Solutions Architect",
+                "print_project_scope",
+            ))
+            .await
+            .unwrap();
+        provider
+            .chat_completion(&chat_completion("Backend Developer", "print_fixed_code"))
+            .await
+            .unwrap_err();
+
+        let entries = read_transcript(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].ai_function, "print_project_scope");
+        assert_eq!(entries[0].outcome, Outcome::Success);
+        assert_eq!(entries[0].served_by.as_deref(), Some("fake-model"));
+        assert_eq!(entries[0].usage.unwrap().completion_tokens, 2);
+        assert_eq!(
+            entries[0].response.as_ref().unwrap().content,
+            "{\"is_crud_required\": true}"
+        );
+        assert_eq!(entries[1].outcome, Outcome::Error);
+        assert!(entries[1].error.as_ref().unwrap().contains("no reply"));
+
+        let errors = TranscriptFilter {
+            errors_only: true,
+            ..TranscriptFilter::default()
+        };
+        let backend = TranscriptFilter {
+            agent_position: Some("Backend Developer".to_string()),
+            ..TranscriptFilter::default()
+        };
+        assert!(!errors.matches(&entries[0]) && errors.matches(&entries[1]));
+        assert!(!backend.matches(&entries[0]) && backend.matches(&entries[1]));
+
+        fs::remove_file(&path).ok();
+    }
+}
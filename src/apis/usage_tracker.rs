@@ -0,0 +1,47 @@
+use crate::apis::llm_error::LlmError;
+use crate::apis::llm_provider::{LlmProvider, StructuredOutput};
+use crate::models::general::llm::{APIResponse, ChatCompletion};
+use crate::models::general::usage::UsageLedger;
+use async_trait::async_trait;
+
+use std::sync::Arc;
+
+// Records the token usage of every successful call in a UsageLedger
+#[derive(Debug)]
+pub struct UsageTrackingProvider {
+    inner: Arc<dyn LlmProvider>,
+    ledger: Arc<UsageLedger>,
+}
+
+impl UsageTrackingProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, ledger: Arc<UsageLedger>) -> Self {
+        Self { inner, ledger }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for UsageTrackingProvider {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.inner.structured_output()
+    }
+
+    async fn chat_completion(
+        &self,
+        chat_completion: &ChatCompletion,
+    ) -> Result<APIResponse, LlmError> {
+        let res: APIResponse = self.inner.chat_completion(chat_completion).await?;
+
+        // Priced by the model that served the call - a fallback may have answered
+        self.ledger.record(
+            &chat_completion.meta,
+            res.model.as_deref().unwrap_or(&chat_completion.model),
+            &res.usage.unwrap_or_default(),
+        );
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::apis::llm_error::LlmError;
+
+use std::fmt;
+
+// Crate wide error returned by helpers and agents
+#[derive(Debug)]
+pub enum AutoGptError {
+    // LLM provider could not produce a response
+    Provider(LlmError),
+    // LLM response did not decode into the expected type, repairs included
+    Decode {
+        ai_function: String,
+        response: String,
+        attempts: u32,
+        source: serde_json::Error,
+    },
+    // LLM response was still cut off at max_tokens after the allowed continuations
+    Truncated {
+        ai_function: String,
+        continuations: u32,
+        response: String,
+    },
+    // Reading or writing a file failed
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    // Missing or invalid configuration
+    Config(String),
+    // Agent could not complete its work
+    Agent {
+        position: String,
+        message: String,
+    },
+}
+
+impl AutoGptError {
+    // Errors that will fail every later agent as well - the run should stop
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Self::Provider(_) | Self::Io { .. } | Self::Config(_))
+    }
+}
+
+impl fmt::Display for AutoGptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Provider(e) => write!(f, "LLM provider error: {}", e),
+            Self::Decode {
+                ai_function,
+                response,
+                attempts,
+                source,
+            } => {
+                let snippet: String = response.chars().take(200).collect();
+                write!(
+                    f,
+                    "Failed to decode {} response after {} attempts: {} - last response was: {}",
+                    ai_function, attempts, source, snippet
+                )
+            }
+            Self::Truncated {
+                ai_function,
+                continuations,
+                response,
+            } => write!(
+                f,
+                "{} response was cut off at max_tokens after {} continuations ({} chars) - raise max_tokens or max_continuations",
+                ai_function,
+                continuations,
+                response.chars().count()
+            ),
+            Self::Io { path, source } => write!(f, "File error at {}: {}", path, source),
+            Self::Config(e) => write!(f, "Configuration error: {}", e),
+            Self::Agent { position, message } => write!(f, "{} failed: {}", position, message),
+        }
+    }
+}
+
+impl std::error::Error for AutoGptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Provider(e) => Some(e),
+            Self::Decode { source, .. } => Some(source),
+            Self::Io { source, .. } => Some(source),
+            Self::Truncated { .. } | Self::Config(_) | Self::Agent { .. } => None,
+        }
+    }
+}
+
+impl From<LlmError> for AutoGptError {
+    fn from(e: LlmError) -> Self {
+        Self::Provider(e)
+    }
+}
@@ -1,5 +1,15 @@
-use std::io::{stdin, stdout};
+use std::io::{stdin, stdout, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::apis::cassette::{CassetteMode, CassetteProvider};
+use crate::apis::llm_provider::{provider_from_env, LlmProvider};
+use crate::apis::transcript::{read_transcript, Outcome, TranscriptEntry, TranscriptFilter};
+use crate::errors::AutoGptError;
+use crate::helpers::evaluation::{read_dataset, run_eval, EvalCase, EvalReport};
+use crate::helpers::prompt_templates::export_templates;
+use crate::models::general::llm_config::LlmConfig;
+use crate::models::general::usage::{UsageSummary, UsageTotals};
 use crossterm::{
     style::{Color, ResetColor, SetForegroundColor},
     ExecutableCommand,
@@ -36,6 +46,275 @@ impl PrintCommand {
         // Reset color
         stdout.execute(ResetColor).unwrap();
     }
+
+    // Rewrite the current line with the number of characters streamed so far
+    pub fn print_stream_progress(&self, agent_pos: &str, received_chars: usize) {
+        let mut stdout: std::io::Stdout = stdout();
+
+        stdout.execute(SetForegroundColor(Color::Green)).unwrap();
+        print!("\rAgent: {}: ", agent_pos);
+
+        stdout.execute(SetForegroundColor(Color::Cyan)).unwrap();
+        print!("receiving... {} chars", received_chars);
+
+        stdout.execute(ResetColor).unwrap();
+        stdout.flush().unwrap();
+    }
+
+    // End the progress line once the stream is complete
+    pub fn finish_stream_progress(&self) {
+        println!();
+    }
+}
+
+// Print token usage and cost of a run
+pub fn print_usage_summary(summary: &UsageSummary) {
+    let mut stdout: std::io::Stdout = stdout();
+
+    let line = |name: &str, totals: &UsageTotals| {
+        println!(
+            "  {:<40} calls: {:>3}  prompt: {:>7}  completion: {:>7}  cost: ${:.4}",
+            name, totals.calls, totals.prompt_tokens, totals.completion_tokens, totals.cost_usd
+        );
+    };
+
+    stdout.execute(SetForegroundColor(Color::Yellow)).unwrap();
+
+    println!();
+    println!("Usage per agent:");
+    for (agent, totals) in &summary.by_agent {
+        line(agent, totals);
+    }
+    println!("Usage per ai function:");
+    for (function, totals) in &summary.by_function {
+        line(function, totals);
+    }
+    println!("Project total:");
+    line("all agents", &summary.project);
+
+    if !summary.unpriced_models.is_empty() {
+        println!(
+            "No price configured for: {}",
+            summary.unpriced_models.join(", ")
+        );
+    }
+
+    // Reset Color
+    stdout.execute(ResetColor).unwrap();
+}
+
+// Print one transcript entry - full also prints the prompt and the response
+pub fn print_transcript_entry(entry: &TranscriptEntry, full: bool) {
+    let mut stdout: std::io::Stdout = stdout();
+
+    let outcome_color: Color = match entry.outcome {
+        Outcome::Success => Color::Cyan,
+        Outcome::Error => Color::Red,
+    };
+    let tokens: String = entry.usage.map_or("-".to_string(), |usage| {
+        format!("{}+{}", usage.prompt_tokens, usage.completion_tokens)
+    });
+    let model: String = match &entry.served_by {
+        Some(served_by) if served_by != &entry.model => {
+            format!("{} (served by {})", entry.model, served_by)
+        }
+        _ => entry.model.clone(),
+    };
+
+    stdout.execute(SetForegroundColor(Color::Green)).unwrap();
+    print!(
+        "[{}] {} / {} ",
+        entry.timestamp, entry.agent_position, entry.ai_function
+    );
+
+    stdout.execute(SetForegroundColor(outcome_color)).unwrap();
+    println!(
+        "{} {}ms tokens: {} {:?}",
+        model, entry.latency_ms, tokens, entry.outcome
+    );
+
+    stdout.execute(ResetColor).unwrap();
+
+    if let Some(error) = &entry.error {
+        println!("  error: {}", error);
+    }
+
+    if full {
+        for message in &entry.request {
+            println!("  --- {} ---\n{}", message.role, message.content);
+        }
+        if let Some(response) = &entry.response {
+            println!("  --- response ---\n{}", response.content);
+            for call in response.tool_calls.iter().flatten() {
+                println!(
+                    "  --- tool call {} ---\n{}",
+                    call.function.name, call.function.arguments
+                );
+            }
+        }
+        println!();
+    }
+}
+
+// transcript <file> [--agent <position>] [--function <ai function>] [--errors] [--full]
+pub fn run_transcript_command(args: &[String]) -> Result<(), AutoGptError> {
+    let usage_error = || {
+        AutoGptError::Config(
+            "Usage: transcript <file> [--agent <position>] [--function <ai function>] [--errors] [--full]"
+                .to_string(),
+        )
+    };
+
+    let mut path: Option<&str> = None;
+    let mut filter = TranscriptFilter::default();
+    let mut full: bool = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--agent" => filter.agent_position = Some(args.next().ok_or_else(usage_error)?.clone()),
+            "--function" => filter.ai_function = Some(args.next().ok_or_else(usage_error)?.clone()),
+            "--errors" => filter.errors_only = true,
+            "--full" => full = true,
+            other if path.is_none() && !other.starts_with("--") => path = Some(other),
+            _ => return Err(usage_error()),
+        }
+    }
+
+    let entries: Vec<TranscriptEntry> = read_transcript(Path::new(path.ok_or_else(usage_error)?))?;
+    for entry in entries.iter().filter(|entry| filter.matches(entry)) {
+        print_transcript_entry(entry, full);
+    }
+
+    Ok(())
+}
+
+// prompts export [dir] - writes the built-in prompt templates for editing
+// One <ai function>.txt per function: the shared wrapper around that function's text
+// dir defaults to the configured prompt directory
+pub fn run_prompts_command(args: &[String]) -> Result<(), AutoGptError> {
+    let usage_error = || AutoGptError::Config("Usage: prompts export [<dir>]".to_string());
+
+    let llm_config: LlmConfig = LlmConfig::from_env()?;
+    let dir: &Path = match args {
+        [command] if command == "export" => llm_config.prompt_dir(),
+        [command, dir] if command == "export" => Path::new(dir),
+        _ => return Err(usage_error()),
+    };
+
+    let written: Vec<PathBuf> = export_templates(dir)?;
+    for path in &written {
+        println!("Wrote {}", path.display());
+    }
+    println!(
+        "{} templates written to {} - existing files were kept",
+        written.len(),
+        dir.display()
+    );
+
+    Ok(())
+}
+
+// Print per-case results of one or two eval runs side by side, then aggregate accuracy
+pub fn print_eval_reports(reports: &[EvalReport]) {
+    let mut stdout: std::io::Stdout = stdout();
+
+    let Some(first) = reports.first() else {
+        return;
+    };
+
+    for (i, result) in first.results.iter().enumerate() {
+        print!("{:<32}", result.name);
+        for report in reports {
+            let result = &report.results[i];
+            let (color, verdict) = match result.passed() {
+                true => (Color::Green, "PASS"),
+                false => (Color::Red, "FAIL"),
+            };
+            stdout.execute(SetForegroundColor(color)).unwrap();
+            print!("  {}: {}", report.label, verdict);
+            stdout.execute(ResetColor).unwrap();
+        }
+        println!();
+
+        for report in reports {
+            for failure in &report.results[i].failures {
+                println!("    {}: {}", report.label, failure);
+            }
+        }
+    }
+
+    stdout.execute(SetForegroundColor(Color::Yellow)).unwrap();
+    println!();
+    for report in reports {
+        let passed: usize = report.results.iter().filter(|r| r.passed()).count();
+        println!(
+            "{}: {}/{} cases passed ({:.1}%), {:.1}% of checks",
+            report.label,
+            passed,
+            report.results.len(),
+            report.accuracy() * 100.0,
+            report.check_accuracy() * 100.0
+        );
+    }
+    stdout.execute(ResetColor).unwrap();
+}
+
+// eval <dataset> [--prompts <dir>] [--compare <dir>] [--cassette <path>]
+// Runs a JSONL golden dataset (see helpers::evaluation) through the configured provider,
+// or only from a recorded cassette, with the prompts in --prompts (default: configured)
+// --compare runs it again with the prompts in another directory, reported side by side
+// A cassette recorded during a --compare run (LLM_CASSETTE_MODE=record) holds the replies to
+// both prompt versions, so replaying it compares them deterministically
+pub async fn run_eval_command(args: &[String]) -> Result<(), AutoGptError> {
+    let usage_error = || {
+        AutoGptError::Config(
+            "Usage: eval <dataset> [--prompts <dir>] [--compare <dir>] [--cassette <path>]"
+                .to_string(),
+        )
+    };
+
+    let mut dataset: Option<&str> = None;
+    let mut prompts: Option<&str> = None;
+    let mut compare: Option<&str> = None;
+    let mut cassette: Option<&str> = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--prompts" => prompts = Some(args.next().ok_or_else(usage_error)?),
+            "--compare" => compare = Some(args.next().ok_or_else(usage_error)?),
+            "--cassette" => cassette = Some(args.next().ok_or_else(usage_error)?),
+            other if dataset.is_none() && !other.starts_with("--") => dataset = Some(other),
+            _ => return Err(usage_error()),
+        }
+    }
+
+    let cases: Vec<EvalCase> = read_dataset(Path::new(dataset.ok_or_else(usage_error)?))?;
+
+    let mut llm_config: LlmConfig = LlmConfig::from_env()?;
+    if let Some(dir) = prompts {
+        llm_config.prompt_dir = Some(PathBuf::from(dir));
+    }
+
+    // Fresh responses - a cached reply would hide the effect of a prompt change
+    let mut provider: Arc<dyn LlmProvider> = provider_from_env(true, &llm_config)?;
+    if let Some(path) = cassette {
+        provider = Arc::new(CassetteProvider::new(provider, CassetteMode::Replay, path)?);
+    }
+
+    let mut reports: Vec<EvalReport> = vec![];
+    let label: String = llm_config.prompt_dir().display().to_string();
+    reports.push(run_eval(provider.as_ref(), &llm_config, &cases, &label).await);
+
+    if let Some(dir) = compare {
+        llm_config.prompt_dir = Some(PathBuf::from(dir));
+        reports.push(run_eval(provider.as_ref(), &llm_config, &cases, dir).await);
+    }
+
+    print_eval_reports(&reports);
+
+    Ok(())
 }
 
 // Get user request
@@ -58,7 +337,7 @@ pub fn get_user_response(question: &str) -> String {
         .expect("Failed to read response");
 
     // Trim whitespace and return
-    return user_response.trim().to_string();
+    user_response.trim().to_string()
 }
 
 #[cfg(test)]
@@ -0,0 +1,455 @@
+use super::command_line::PrintCommand;
+use super::prompt_injection::{delimit_input, INPUT_REFERENCE};
+use crate::apis::llm_provider::LlmProvider;
+use crate::errors::AutoGptError;
+use crate::models::general::llm::{ChatCompletion, Message, RequestMeta};
+use crate::models::general::llm_config::{ContextStrategy, LlmConfig, LlmSettings};
+
+// Room left for the reply when the request does not set max_tokens
+const DEFAULT_COMPLETION_RESERVE: usize = 512;
+
+// Chat format overhead (OpenAI counts 3 tokens per message and 3 to prime the reply)
+const TOKENS_PER_MESSAGE: usize = 3;
+const TOKENS_PER_REPLY: usize = 3;
+
+const SUMMARY_FUNCTION: &str = "summarize_older_turns";
+
+// Tokens of a text - cl100k_base is used for every model, which is close enough for
+// other tokenizers to keep prompts inside the window
+pub fn count_tokens(text: &str) -> usize {
+    tiktoken_rs::cl100k_base_singleton()
+        .lock()
+        .encode_with_special_tokens(text)
+        .len()
+}
+
+// Tokens of a chat prompt including the per message overhead
+pub fn count_message_tokens(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|m| TOKENS_PER_MESSAGE + count_tokens(&m.role) + count_tokens(&m.content))
+        .sum::<usize>()
+        + TOKENS_PER_REPLY
+}
+
+// Shrink the prompt until it fits the model's context window
+// The configured strategies run in order and stop as soon as the prompt fits
+// Returns the summary that replaced the older turns, if they were summarized
+pub async fn fit_context_window(
+    provider: &dyn LlmProvider,
+    llm_config: &LlmConfig,
+    chat_completion: &mut ChatCompletion,
+) -> Result<Option<Message>, AutoGptError> {
+    let settings: LlmSettings = llm_config.resolve(
+        &chat_completion.meta.agent_position,
+        &chat_completion.meta.ai_function,
+    );
+    let context_window: usize = llm_config.context_window(&chat_completion.model);
+    let reserve: usize = chat_completion
+        .max_tokens
+        .map_or(DEFAULT_COMPLETION_RESERVE, |t| t as usize);
+    let budget: usize = context_window.saturating_sub(reserve);
+
+    let tokens: usize = count_message_tokens(&chat_completion.messages);
+    if tokens <= budget {
+        return Ok(None);
+    }
+
+    let agent_position: String = chat_completion.meta.agent_position.clone();
+    PrintCommand::Issue.print_agent_message(
+        &agent_position,
+        &format!(
+            "Prompt of {} tokens exceeds the {} token budget of {}",
+            tokens, budget, chat_completion.model
+        ),
+    );
+
+    let mut summary: Option<Message> = None;
+    for strategy in &settings.context_strategies {
+        match strategy {
+            ContextStrategy::TruncateCodeBlocks => {
+                let older: usize = request_start(&chat_completion.messages);
+                truncate_code_blocks(&mut chat_completion.messages[..older], budget / 4)
+            }
+            ContextStrategy::SummarizeOlderTurns => {
+                if let Some(message) =
+                    summarize_older_turns(provider, chat_completion, budget).await?
+                {
+                    summary = Some(message);
+                }
+            }
+            ContextStrategy::DropOldest => drop_oldest(&mut chat_completion.messages, budget),
+        }
+
+        let tokens: usize = count_message_tokens(&chat_completion.messages);
+        PrintCommand::Issue.print_agent_message(
+            &agent_position,
+            &format!("Applied {:?}, prompt is now {} tokens", strategy, tokens),
+        );
+        if tokens <= budget {
+            return Ok(summary);
+        }
+    }
+
+    // Let the provider decide - it may still accept a slightly larger prompt
+    PrintCommand::Issue.print_agent_message(
+        &agent_position,
+        "Prompt still exceeds the context window after all strategies",
+    );
+    Ok(summary)
+}
+
+// Shorten fenced code blocks over max_block_tokens, keeping their first and last lines
+// Fences only open and close at the start of a line, so ``` inside a sentence is left alone
+// Only called on messages before the current request
+fn truncate_code_blocks(messages: &mut [Message], max_block_tokens: usize) {
+    for message in messages.iter_mut() {
+        let mut lines: Vec<String> = vec![];
+        let mut block: Option<Vec<&str>> = None;
+
+        for line in message.content.split('\n') {
+            let is_fence: bool = line.trim_start().starts_with("```");
+            match block.as_mut() {
+                None if is_fence => {
+                    lines.push(line.to_string());
+                    block = Some(vec![]);
+                }
+                None => lines.push(line.to_string()),
+                Some(code) if !is_fence => code.push(line),
+                Some(code) => {
+                    lines.extend(truncate_lines(code, max_block_tokens));
+                    lines.push(line.to_string());
+                    block = None;
+                }
+            }
+        }
+
+        // A fence that is never closed is not a code block
+        if let Some(code) = block {
+            lines.extend(code.iter().map(|line| line.to_string()));
+        }
+
+        message.content = lines.join("\n");
+    }
+}
+
+fn truncate_lines(lines: &[&str], max_tokens: usize) -> Vec<String> {
+    if count_tokens(&lines.join("\n")) <= max_tokens {
+        return lines.iter().map(|line| line.to_string()).collect();
+    }
+
+    let half: usize = max_tokens / 2;
+
+    let mut head: usize = 0;
+    let mut used: usize = 0;
+    while head < lines.len() {
+        used += count_tokens(lines[head]) + 1;
+        if used > half {
+            break;
+        }
+        head += 1;
+    }
+
+    let mut tail: usize = lines.len();
+    let mut used: usize = 0;
+    while tail > head {
+        used += count_tokens(lines[tail - 1]) + 1;
+        if used > half {
+            break;
+        }
+        tail -= 1;
+    }
+
+    lines[..head]
+        .iter()
+        .map(|line| line.to_string())
+        .chain([format!("// ... {} lines truncated ...", tail - head)])
+        .chain(lines[tail..].iter().map(|line| line.to_string()))
+        .collect()
+}
+
+// The first max_tokens tokens of a text
+fn truncate_to_tokens(text: &str, max_tokens: usize) -> String {
+    let singleton = tiktoken_rs::cl100k_base_singleton();
+    let bpe = singleton.lock();
+    let tokens: Vec<usize> = bpe.encode_with_special_tokens(text);
+    if tokens.len() <= max_tokens {
+        return text.to_string();
+    }
+
+    // A cut inside a multi byte character does not decode - back off a token at a time
+    (0..=max_tokens)
+        .rev()
+        .find_map(|n| bpe.decode(tokens[..n].to_vec()).ok())
+        .unwrap_or_default()
+}
+
+// The current request - every message since the last assistant reply
+// (instructions, delimited input and any output format instructions)
+pub fn current_request(messages: &[Message]) -> &[Message] {
+    &messages[request_start(messages)..]
+}
+
+fn request_start(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .rposition(|m| m.role == "assistant")
+        .map_or(0, |i| i + 1)
+}
+
+// Request summarizing part of the earlier exchanges
+fn summary_request(chat_completion: &ChatCompletion, transcript: &str) -> ChatCompletion {
+    ChatCompletion {
+        model: chat_completion.model.clone(),
+        // The exchanges carry user input, so they go delimited in a user message
+        messages: vec![
+            Message {
+                role: "system".to_string(),
+                content: format!(
+                    "Summarize the earlier exchanges in {} in a few paragraphs. Keep every decision,
+                requirement and name that later work must stay consistent with.",
+                    INPUT_REFERENCE
+                ),
+            },
+            Message {
+                role: "user".to_string(),
+                content: delimit_input(transcript),
+            },
+        ],
+        temperature: 0.0,
+        meta: RequestMeta {
+            agent_position: chat_completion.meta.agent_position.clone(),
+            ai_function: SUMMARY_FUNCTION.to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+// Transcripts of the older messages, split so each fits one summary request
+// A single message too long for a request of its own is cut to fit
+fn summary_chunks(
+    chat_completion: &ChatCompletion,
+    older: &[Message],
+    budget: usize,
+) -> Vec<String> {
+    let overhead: usize = count_message_tokens(&summary_request(chat_completion, "").messages);
+    // Room for the escaping of delimiters in the input
+    let max_message_tokens: usize = budget.saturating_sub(overhead) * 9 / 10;
+    let fits = |transcript: &str| {
+        count_message_tokens(&summary_request(chat_completion, transcript).messages) <= budget
+    };
+
+    let mut chunks: Vec<String> = vec![];
+    for message in older {
+        let turn: String = truncate_to_tokens(
+            &format!("{}: {}", message.role.to_uppercase(), message.content),
+            max_message_tokens,
+        );
+
+        match chunks.last_mut() {
+            Some(chunk) if fits(&format!("{}\n\n{}", chunk, turn)) => {
+                chunk.push_str("\n\n");
+                chunk.push_str(&turn);
+            }
+            _ => chunks.push(turn),
+        }
+    }
+    chunks
+}
+
+// Replace everything before the current request with one summary message
+// Older turns too long for one summary request are summarized a chunk at a time
+async fn summarize_older_turns(
+    provider: &dyn LlmProvider,
+    chat_completion: &mut ChatCompletion,
+    budget: usize,
+) -> Result<Option<Message>, AutoGptError> {
+    let older: usize = request_start(&chat_completion.messages);
+    if older < 2 {
+        return Ok(None);
+    }
+
+    let mut summaries: Vec<String> = vec![];
+    for chunk in summary_chunks(chat_completion, &chat_completion.messages[..older], budget) {
+        let summary: String = provider
+            .chat_completion(&summary_request(chat_completion, &chunk))
+            .await?
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .unwrap_or_default();
+        summaries.push(summary);
+    }
+
+    let summary = Message {
+        role: "system".to_string(),
+        content: format!("SUMMARY OF EARLIER EXCHANGES: {}", summaries.join("\n\n")),
+    };
+    chat_completion.messages.splice(..older, [summary.clone()]);
+    Ok(Some(summary))
+}
+
+// Leave out the oldest messages - the current request is always kept
+fn drop_oldest(messages: &mut Vec<Message>, budget: usize) {
+    while request_start(messages) > 0 && count_message_tokens(messages) > budget {
+        messages.remove(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::fake_provider::FakeProvider;
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn chat_completion(messages: Vec<Message>, strategies: &str) -> (ChatCompletion, LlmConfig) {
+        let llm_config: LlmConfig = serde_json::from_str(&format!(
+            r#"{{
+                "default": {{ "context_strategies": {} }},
+                "context_windows": {{ "small-model": 800 }}
+            }}"#,
+            strategies
+        ))
+        .unwrap();
+
+        let chat_completion = ChatCompletion {
+            model: "small-model".to_string(),
+            messages,
+            max_tokens: Some(100),
+            meta: RequestMeta {
+                agent_position: "Backend Developer".to_string(),
+                ai_function: "print_fixed_code".to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        (chat_completion, llm_config)
+    }
+
+    #[tokio::test]
+    async fn tests_truncate_large_code_block() {
+        let code: String = (0..400)
+            .map(|i| format!("let value_{} = {};", i, i))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let fix_code: String = format!("FIX THIS CODE:\n```rust\n{}\n```", code);
+        // ``` inside a line does not open a block
+        let inline_fence: String = format!("Wrap code in ``` fences\n```rust\n{}\n```", code);
+        let (mut request, llm_config) = chat_completion(
+            vec![
+                message("system", &fix_code),
+                message("assistant", "Fixed"),
+                message("system", &inline_fence),
+                message("assistant", "Done"),
+                message("system", "Add a health check route"),
+            ],
+            r#"["truncate_code_blocks"]"#,
+        );
+        let provider = FakeProvider::new(vec![]);
+
+        fit_context_window(&provider, &llm_config, &mut request)
+            .await
+            .unwrap();
+
+        assert!(count_message_tokens(&request.messages) <= 700);
+        for content in [&request.messages[0].content, &request.messages[2].content] {
+            assert!(content.contains("```rust\nlet value_0 = 0;"));
+            assert!(content.contains("lines truncated"));
+            assert!(content.ends_with("let value_399 = 399;\n```"));
+        }
+        assert!(request.messages[0].content.starts_with("FIX THIS CODE:\n"));
+        assert!(request.messages[2]
+            .content
+            .starts_with("Wrap code in ``` fences\n"));
+
+        // Code in the current request is sent whole, even when it does not fit
+        let (mut request, llm_config) = chat_completion(
+            vec![message("system", &fix_code)],
+            r#"["truncate_code_blocks"]"#,
+        );
+        fit_context_window(&provider, &llm_config, &mut request)
+            .await
+            .unwrap();
+        assert_eq!(request.messages[0].content, fix_code);
+    }
+
+    #[tokio::test]
+    async fn tests_summarize_then_drop_oldest() {
+        let long: String = "earlier decision ".repeat(100);
+        let request: String = format!("Fix the bugs {}", "in the handler ".repeat(40));
+        let messages = vec![
+            message("system", &long),
+            message("assistant", &long),
+            message("assistant", &long),
+            message("system", &request),
+        ];
+
+        // Summary replaces the older turns
+        let (mut request, llm_config) =
+            chat_completion(messages.clone(), r#"["summarize_older_turns"]"#);
+        assert!(count_message_tokens(&messages) > 700);
+        let provider = FakeProvider::new(vec!["Use actix and a json database"]);
+        fit_context_window(&provider, &llm_config, &mut request)
+            .await
+            .unwrap();
+
+        assert_eq!(request.messages.len(), 2);
+        assert_eq!(
+            request.messages[0].content,
+            "SUMMARY OF EARLIER EXCHANGES: Use actix and a json database"
+        );
+        assert_eq!(request.messages[1].content, messages[3].content);
+        assert_eq!(
+            provider.requests.lock().unwrap()[0].meta.ai_function,
+            SUMMARY_FUNCTION
+        );
+
+        // Dropping keeps the newest messages that fit
+        let (mut request, llm_config) = chat_completion(messages.clone(), r#"["drop_oldest"]"#);
+        fit_context_window(&FakeProvider::new(vec![]), &llm_config, &mut request)
+            .await
+            .unwrap();
+
+        assert_eq!(request.messages[..], messages[1..]);
+    }
+
+    #[tokio::test]
+    async fn tests_summarize_older_turns_over_budget() {
+        // The older turns alone are more than one summary request can take
+        let long: String = "earlier decision ".repeat(500);
+        let messages = vec![
+            message("system", &long),
+            message("assistant", &"the chosen design ".repeat(150)),
+            message("system", "Add a health check route"),
+        ];
+        let (mut request, llm_config) = chat_completion(messages, r#"["summarize_older_turns"]"#);
+        assert!(count_message_tokens(&request.messages[..2]) > 700);
+
+        let provider = FakeProvider::new(vec!["Use actix", "Store todos in json"]);
+        let summary: Option<Message> = fit_context_window(&provider, &llm_config, &mut request)
+            .await
+            .unwrap();
+
+        // Summarized a chunk at a time, each request inside the budget
+        let summary_requests = provider.requests.lock().unwrap();
+        assert_eq!(summary_requests.len(), 2);
+        assert!(summary_requests
+            .iter()
+            .all(|r| count_message_tokens(&r.messages) <= 700));
+        assert_eq!(
+            request.messages[0].content,
+            "SUMMARY OF EARLIER EXCHANGES: Use actix\n\nStore todos in json"
+        );
+        assert_eq!(request.messages[1].content, "Add a health check route");
+        assert_eq!(summary.as_ref(), Some(&request.messages[0]));
+    }
+}
@@ -0,0 +1,366 @@
+use super::general::{ai_task_request, ai_task_request_decoded};
+use super::prompt_templates::{AiFunction, AI_FUNCTIONS};
+use crate::apis::llm_provider::LlmProvider;
+use crate::errors::AutoGptError;
+use crate::models::agents::agent_architect::SOLUTIONS_ARCHITECT;
+use crate::models::agents::agent_backend::BACKEND_DEVELOPER;
+use crate::models::agents::agent_traits::{ProjectScope, RouteObject};
+use crate::models::agents_manager::managing_agent::PROJECT_MANAGER;
+use crate::models::general::llm_config::LlmConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// What a correct output looks like
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Expectation {
+    // The whole output
+    Equals(Value),
+    // Each listed field of an object output - other fields are not checked
+    Fields(Map<String, Value>),
+    // Text output, or the JSON text of structured output
+    StartsWith(String),
+    Contains(String),
+}
+
+// One line of a golden dataset
+// {"name": "todo", "ai_function": "print_project_scope", "input": "I need a simple TODO app",
+//  "expect": {"fields": {"is_crud_required": true, "is_user_login_and_logout": false}}}
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EvalCase {
+    pub name: String,
+    pub ai_function: String,
+    pub input: String,
+    pub expect: Expectation,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseResult {
+    pub name: String,
+    pub checks: usize,
+    // One entry per failed check, or the error that stopped the case
+    pub failures: Vec<String>,
+    pub output: Option<Value>,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+// Results of one dataset run with one set of prompts
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalReport {
+    pub label: String,
+    pub results: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    // Share of cases that passed every check
+    pub fn accuracy(&self) -> f32 {
+        let passed: usize = self.results.iter().filter(|r| r.passed()).count();
+        passed as f32 / self.results.len().max(1) as f32
+    }
+
+    // Share of individual checks (fields) that passed
+    pub fn check_accuracy(&self) -> f32 {
+        let checks: usize = self.results.iter().map(|r| r.checks).sum();
+        let failed: usize = self
+            .results
+            .iter()
+            .map(|r| r.failures.len().min(r.checks))
+            .sum();
+        (checks - failed) as f32 / checks.max(1) as f32
+    }
+}
+
+// Read a JSONL golden dataset
+pub fn read_dataset(path: &Path) -> Result<Vec<EvalCase>, AutoGptError> {
+    let io_error = |source: io::Error| AutoGptError::Io {
+        path: path.display().to_string(),
+        source,
+    };
+
+    fs::read_to_string(path)
+        .map_err(io_error)?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                io_error(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", i + 1, e),
+                ))
+            })
+        })
+        .collect()
+}
+
+// Failed checks of an output and the number of checks made
+pub fn score(expect: &Expectation, output: &Value) -> (usize, Vec<String>) {
+    // Text checks on structured output look at its JSON
+    let text: String = match output {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+
+    match expect {
+        Expectation::Equals(expected) if expected == output => (1, vec![]),
+        Expectation::Equals(expected) => (1, vec![format!("expected {}", expected)]),
+        Expectation::Fields(fields) => {
+            let failures: Vec<String> = fields
+                .iter()
+                .filter(|(name, expected)| output.get(name.as_str()) != Some(*expected))
+                .map(|(name, expected)| {
+                    format!(
+                        "{}: expected {}, got {}",
+                        name,
+                        expected,
+                        output.get(name.as_str()).unwrap_or(&Value::Null)
+                    )
+                })
+                .collect();
+            (fields.len(), failures)
+        }
+        Expectation::StartsWith(prefix) if text.starts_with(prefix.as_str()) => (1, vec![]),
+        Expectation::StartsWith(prefix) => (1, vec![format!("does not start with {:?}", prefix)]),
+        Expectation::Contains(part) if text.contains(part.as_str()) => (1, vec![]),
+        Expectation::Contains(part) => (1, vec![format!("does not contain {:?}", part)]),
+    }
+}
+
+// Position of the agent that calls an ai function - LlmConfig settings resolve per agent
+fn owning_agent_position(ai_function: &str) -> &'static str {
+    match ai_function {
+        "convert_user_input_to_goal" => PROJECT_MANAGER,
+        "print_project_scope" | "print_site_urls" => SOLUTIONS_ARCHITECT,
+        _ => BACKEND_DEVELOPER,
+    }
+}
+
+// Output of an ai function as JSON - decoded functions as their target type, others as text
+async fn run_case(
+    provider: &dyn LlmProvider,
+    llm_config: &LlmConfig,
+    case: &EvalCase,
+) -> Result<Value, AutoGptError> {
+    let function_pass: AiFunction = AI_FUNCTIONS
+        .iter()
+        .find(|(name, _)| *name == case.ai_function)
+        .map(|(_, function_pass)| *function_pass)
+        .ok_or_else(|| AutoGptError::Config(format!("Unknown ai function {}", case.ai_function)))?;
+
+    let (input, name) = (case.input.clone(), case.ai_function.as_str());
+    let position: &str = owning_agent_position(name);
+
+    let output: Value = match name {
+        "print_project_scope" => serde_json::to_value(
+            ai_task_request_decoded::<ProjectScope>(
+                provider,
+                llm_config,
+                input,
+                position,
+                function_pass,
+            )
+            .await?,
+        ),
+        "print_site_urls" => serde_json::to_value(
+            ai_task_request_decoded::<Vec<String>>(
+                provider,
+                llm_config,
+                input,
+                position,
+                function_pass,
+            )
+            .await?,
+        ),
+        "print_rest_api_endpoints" => serde_json::to_value(
+            ai_task_request_decoded::<Vec<RouteObject>>(
+                provider,
+                llm_config,
+                input,
+                position,
+                function_pass,
+            )
+            .await?,
+        ),
+        _ => Ok(Value::String(
+            ai_task_request(provider, llm_config, input, position, function_pass).await?,
+        )),
+    }
+    .map_err(|e| AutoGptError::Agent {
+        position: position.to_string(),
+        message: format!("Could not convert the {} output to JSON: {}", name, e),
+    })?;
+
+    Ok(output)
+}
+
+// Run every case - a case that errors fails, the run carries on
+pub async fn run_eval(
+    provider: &dyn LlmProvider,
+    llm_config: &LlmConfig,
+    cases: &[EvalCase],
+    label: &str,
+) -> EvalReport {
+    let mut results: Vec<CaseResult> = vec![];
+
+    for case in cases {
+        let result: CaseResult = match run_case(provider, llm_config, case).await {
+            Ok(output) => {
+                let (checks, failures) = score(&case.expect, &output);
+                CaseResult {
+                    name: case.name.clone(),
+                    checks,
+                    failures,
+                    output: Some(output),
+                }
+            }
+            Err(e) => CaseResult {
+                name: case.name.clone(),
+                checks: 1,
+                failures: vec![e.to_string()],
+                output: None,
+            },
+        };
+        results.push(result);
+    }
+
+    EvalReport {
+        label: label.to_string(),
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::cassette::{CassetteMode, CassetteProvider};
+    use crate::apis::fake_provider::FakeProvider;
+    use crate::helpers::prompt_injection::INPUT_REFERENCE;
+    use std::env;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn tests_scores_cases_and_prompt_versions() {
+        let cases: Vec<EvalCase> = [
+            r#"{"name": "goal", "ai_function": "convert_user_input_to_goal", "input": "a todo app", "expect": {"starts_with": "build a website that"}}"#,
+            r#"{"name": "scope", "ai_function": "print_project_scope", "input": "a todo app", "expect": {"fields": {"is_crud_required": true, "is_user_login_and_logout": false}}}"#,
+            r#"{"name": "unknown", "ai_function": "print_poem", "input": "a todo app", "expect": {"contains": "todo"}}"#,
+        ]
+        .iter()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+        let provider = FakeProvider::new(vec![
+            "build a website that tracks todos",
+            r#"{"is_crud_required": true, "is_user_login_and_logout": true, "is_external_urls_required": false}"#,
+        ]);
+        let report: EvalReport = run_eval(&provider, &LlmConfig::default(), &cases, "A").await;
+
+        let failures: Vec<&Vec<String>> = report.results.iter().map(|r| &r.failures).collect();
+        assert!(failures[0].is_empty());
+        assert_eq!(
+            failures[1],
+            &vec!["is_user_login_and_logout: expected false, got true"]
+        );
+        assert!(failures[2][0].contains("Unknown ai function print_poem"));
+        // Settings resolve as they would for the agent that owns each function
+        let positions: Vec<String> = provider
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| r.meta.agent_position.clone())
+            .collect();
+        assert_eq!(positions, [PROJECT_MANAGER, SOLUTIONS_ARCHITECT]);
+        assert!((report.accuracy() - 1.0 / 3.0).abs() < 1e-6);
+        // 1 of 2 scope fields, the goal check and the failed case
+        assert!((report.check_accuracy() - 2.0 / 4.0).abs() < 1e-6);
+
+        // A second prompt version is read from its own directory
+        let dir: PathBuf = env::temp_dir().join(format!("auto_gpt_eval_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("convert_user_input_to_goal.txt"),
+            "Summarise {{input}} as a goal.",
+        )
+        .unwrap();
+        let llm_config = LlmConfig {
+            prompt_dir: Some(dir.clone()),
+            ..LlmConfig::default()
+        };
+
+        let provider = FakeProvider::new(vec!["a website for todos"]);
+        let report: EvalReport = run_eval(&provider, &llm_config, &cases[..1], "B").await;
+        assert!(!report.results[0].passed());
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(
+            requests[0].messages[0].content,
+            format!("Summarise {} as a goal.", INPUT_REFERENCE)
+        );
+        assert_eq!(
+            requests[0].messages[1].content,
+            "<input>\na todo app\n</input>"
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn tests_replays_compared_prompt_versions() {
+        let dir: PathBuf =
+            env::temp_dir().join(format!("auto_gpt_eval_compare_{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("prompts_b")).unwrap();
+        fs::write(
+            dir.join("prompts_b/convert_user_input_to_goal.txt"),
+            "Summarise {{input}} as a goal.",
+        )
+        .unwrap();
+        let cases: Vec<EvalCase> = vec![serde_json::from_str(
+            r#"{"name": "goal", "ai_function": "convert_user_input_to_goal", "input": "a todo app", "expect": {"starts_with": "build a website that"}}"#,
+        )
+        .unwrap()];
+        let config_a = LlmConfig::default();
+        let config_b = LlmConfig {
+            prompt_dir: Some(dir.join("prompts_b")),
+            ..LlmConfig::default()
+        };
+
+        // Record a compare run, then replay it without the model
+        let fake = Arc::new(FakeProvider::new(vec![
+            "build a website that tracks todos",
+            "a website for todos",
+        ]));
+        let cassette: PathBuf = dir.join("compare.json");
+        let recorder = CassetteProvider::new(fake, CassetteMode::Record, &cassette).unwrap();
+        let recorded: Vec<EvalReport> = vec![
+            run_eval(&recorder, &config_a, &cases, "A").await,
+            run_eval(&recorder, &config_b, &cases, "B").await,
+        ];
+
+        let replayer = CassetteProvider::new(
+            Arc::new(FakeProvider::new(vec![])),
+            CassetteMode::Replay,
+            &cassette,
+        )
+        .unwrap();
+        let replayed: Vec<EvalReport> = vec![
+            run_eval(&replayer, &config_a, &cases, "A").await,
+            run_eval(&replayer, &config_b, &cases, "B").await,
+        ];
+
+        assert_eq!(replayed, recorded);
+        assert!(replayed[0].results[0].passed() && !replayed[1].results[0].passed());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,199 @@
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde_json::{Map, Value};
+
+// JSON Schema of a decoded type - subschemas are inlined so the prompt needs no $ref lookups
+pub fn schema_for<T: JsonSchema>() -> Value {
+    let generator = SchemaSettings::draft07()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .into_generator();
+
+    serde_json::to_value(generator.into_root_schema_for::<T>()).unwrap_or(Value::Bool(true))
+}
+
+// Check a value against the parts of JSON Schema that schemars generates -
+// type, properties, required, additionalProperties, items, enum, anyOf, minimum and maximum
+// Returns every violation, each prefixed with the path of the offending value
+pub fn validate(value: &Value, schema: &Value) -> Result<(), Vec<String>> {
+    let mut errors: Vec<String> = vec![];
+    validate_at("$", value, schema, &mut errors);
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn validate_at(path: &str, value: &Value, schema: &Value, errors: &mut Vec<String>) {
+    let schema: &Map<String, Value> = match schema {
+        Value::Object(schema) => schema,
+        // true accepts anything, false nothing
+        Value::Bool(true) => return,
+        _ => {
+            errors.push(format!("{}: no value is allowed here", path));
+            return;
+        }
+    };
+
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+            errors.push(format!(
+                "{}: expected {}, found {}",
+                path,
+                types.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            errors.push(format!(
+                "{}: {} is not one of {}",
+                path,
+                value,
+                Value::Array(allowed.clone())
+            ));
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema.get("anyOf") {
+        let matches_any: bool = options.iter().any(|option| {
+            let mut option_errors: Vec<String> = vec![];
+            validate_at(path, value, option, &mut option_errors);
+            option_errors.is_empty()
+        });
+        if !matches_any {
+            errors.push(format!("{}: does not match any allowed shape", path));
+        }
+    }
+
+    if let Some(number) = value.as_f64() {
+        if let Some(minimum) = schema.get("minimum").and_then(Value::as_f64) {
+            if number < minimum {
+                errors.push(format!(
+                    "{}: {} is below the minimum {}",
+                    path, number, minimum
+                ));
+            }
+        }
+        if let Some(maximum) = schema.get("maximum").and_then(Value::as_f64) {
+            if number > maximum {
+                errors.push(format!(
+                    "{}: {} is above the maximum {}",
+                    path, number, maximum
+                ));
+            }
+        }
+    }
+
+    if let Value::Object(object) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    errors.push(format!("{}: missing required field {}", path, name));
+                }
+            }
+        }
+
+        let properties: Option<&Map<String, Value>> =
+            schema.get("properties").and_then(Value::as_object);
+        for (name, field) in object {
+            let field_path: String = format!("{}.{}", path, name);
+            match (
+                properties.and_then(|p| p.get(name)),
+                schema.get("additionalProperties"),
+            ) {
+                (Some(field_schema), _) => validate_at(&field_path, field, field_schema, errors),
+                (None, Some(Value::Bool(false))) => {
+                    errors.push(format!("{}: unknown field {}", path, name))
+                }
+                (None, Some(additional)) => validate_at(&field_path, field, additional, errors),
+                (None, None) => {}
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate_at(&format!("{}[{}]", path, i), item, item_schema, errors);
+        }
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::agents::agent_traits::{ProjectScope, RouteObject};
+    use serde_json::json;
+
+    #[test]
+    fn tests_schema_from_types_validates_output() {
+        let schema: Value = schema_for::<ProjectScope>();
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["properties"]["is_crud_required"]["type"], "boolean");
+        assert!(schema["properties"]["is_crud_required"]["description"].is_string());
+        assert_eq!(schema["required"].as_array().unwrap().len(), 3);
+
+        let scope = json!({
+            "is_crud_required": "true",
+            "is_user_login_and_logout": false
+        });
+        assert_eq!(
+            validate(&scope, &schema).unwrap_err(),
+            vec![
+                "$: missing required field is_external_urls_required",
+                "$.is_crud_required: expected boolean, found string",
+            ]
+        );
+
+        // Free form request and response bodies accept anything
+        let schema: Value = schema_for::<Vec<RouteObject>>();
+        let routes = json!([
+            {"route": "/item/{id}", "is_route_dynamic": true, "method": "get",
+             "request_body": "None", "response": {"id": "number"}},
+            {"route": "/item", "is_route_dynamic": "false", "method": "post",
+             "request_body": {"id": "number"}, "response": "None"}
+        ]);
+        assert_eq!(
+            validate(&routes, &schema).unwrap_err(),
+            vec!["$[1].is_route_dynamic: expected boolean, found string"]
+        );
+        assert!(validate(&json!([]), &schema).is_ok());
+    }
+}
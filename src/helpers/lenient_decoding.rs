@@ -0,0 +1,200 @@
+use super::json_schema::validate;
+use serde_json::{Map, Number, Value};
+
+use std::fmt;
+
+// One value changed to fit the target type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coercion {
+    pub path: String,
+    pub from: Value,
+    pub to: Value,
+}
+
+impl fmt::Display for Coercion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.path, self.from, self.to)
+    }
+}
+
+// Models often quote everything - bring stringly-typed values in line with the schema of the
+// target type (see helpers::json_schema) before it is validated and deserialized
+// "true"/"false" become booleans, numeric strings numbers, and "None"/"null" placeholders null
+// where the schema allows null or any value. Returns every coercion made
+pub fn coerce_to_schema(value: &mut Value, schema: &Value) -> Vec<Coercion> {
+    let mut coercions: Vec<Coercion> = vec![];
+    coerce_at("$", value, schema, &mut coercions);
+    coercions
+}
+
+fn coerce_at(path: &str, value: &mut Value, schema: &Value, coercions: &mut Vec<Coercion>) {
+    let schema: &Map<String, Value> = match schema {
+        Value::Object(schema) => schema,
+        // Any value - only placeholders are replaced
+        Value::Bool(true) => {
+            if is_null_placeholder(value) {
+                replace(path, value, Value::Null, coercions);
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    if let Some(Value::Array(options)) = schema.get("anyOf") {
+        // First shape the value can be made to fit
+        for option in options {
+            let mut candidate: Value = value.clone();
+            let mut option_coercions: Vec<Coercion> = vec![];
+            coerce_at(path, &mut candidate, option, &mut option_coercions);
+            if validate(&candidate, option).is_ok() {
+                *value = candidate;
+                coercions.extend(option_coercions);
+                return;
+            }
+        }
+    }
+
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(name)) => vec![name.as_str()],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+    };
+
+    if let Value::String(text) = value {
+        if let Some(coerced) = coerce_string(text, &types) {
+            replace(path, value, coerced, coercions);
+            return;
+        }
+    }
+
+    match value {
+        Value::Object(object) => {
+            let properties: Option<&Map<String, Value>> =
+                schema.get("properties").and_then(Value::as_object);
+            for (name, field) in object.iter_mut() {
+                let field_schema: Option<&Value> = properties
+                    .and_then(|p| p.get(name))
+                    .or_else(|| schema.get("additionalProperties"));
+                if let Some(field_schema) = field_schema {
+                    coerce_at(
+                        &format!("{}.{}", path, name),
+                        field,
+                        field_schema,
+                        coercions,
+                    );
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter_mut().enumerate() {
+                    coerce_at(&format!("{}[{}]", path, i), item, item_schema, coercions);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Typed value for a string where the schema does not allow strings
+// No type at all means any value, such as a field described but typed serde_json::Value
+fn coerce_string(text: &str, types: &[&str]) -> Option<Value> {
+    let text: &str = text.trim();
+    if types.is_empty() {
+        return is_placeholder(text).then_some(Value::Null);
+    }
+    if types.contains(&"string") {
+        return None;
+    }
+
+    if types.contains(&"null") && is_placeholder(text) {
+        return Some(Value::Null);
+    }
+    if types.contains(&"boolean") {
+        match text.to_ascii_lowercase().as_str() {
+            "true" => return Some(Value::Bool(true)),
+            "false" => return Some(Value::Bool(false)),
+            _ => {}
+        }
+    }
+    if types.contains(&"integer") {
+        if let Ok(integer) = text.parse::<i64>() {
+            return Some(Value::from(integer));
+        }
+        if let Ok(integer) = text.parse::<u64>() {
+            return Some(Value::from(integer));
+        }
+    }
+    if types.contains(&"number") {
+        if let Some(number) = text.parse::<f64>().ok().and_then(Number::from_f64) {
+            return Some(Value::Number(number));
+        }
+    }
+
+    None
+}
+
+fn is_placeholder(text: &str) -> bool {
+    matches!(text, "None" | "none" | "null" | "NULL" | "Null")
+}
+
+fn is_null_placeholder(value: &Value) -> bool {
+    value
+        .as_str()
+        .is_some_and(|text| is_placeholder(text.trim()))
+}
+
+fn replace(path: &str, value: &mut Value, to: Value, coercions: &mut Vec<Coercion>) {
+    coercions.push(Coercion {
+        path: path.to_string(),
+        from: std::mem::replace(value, to.clone()),
+        to,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::json_schema::schema_for;
+    use crate::models::agents::agent_traits::RouteObject;
+    use serde_json::json;
+
+    #[test]
+    fn tests_coerces_stringly_typed_routes() {
+        // The documented example output of print_rest_api_endpoints
+        let mut routes = json!([
+            {"route": "/item/{id}", "is_route_dynamic": "true", "method": "get",
+             "request_body": "None", "response": {"id": "number"}},
+            {"route": "/item", "is_route_dynamic": "False", "method": "post",
+             "request_body": {"id": "number"}, "response": "null"}
+        ]);
+        let schema: Value = schema_for::<Vec<RouteObject>>();
+
+        let coercions: Vec<Coercion> = coerce_to_schema(&mut routes, &schema);
+        let reported: Vec<String> = coercions.iter().map(Coercion::to_string).collect();
+        assert_eq!(
+            reported,
+            vec![
+                r#"$[0].is_route_dynamic: "true" -> true"#,
+                r#"$[0].request_body: "None" -> null"#,
+                r#"$[1].is_route_dynamic: "False" -> false"#,
+                r#"$[1].response: "null" -> null"#,
+            ]
+        );
+        let routes: Vec<RouteObject> = serde_json::from_value(routes).unwrap();
+        assert!(routes[0].is_route_dynamic && !routes[1].is_route_dynamic);
+        // Strings the schema asks for are left alone
+        assert_eq!(routes[0].route, "/item/{id}");
+
+        let schema = json!({"type": "object", "properties": {
+            "id": {"type": "integer"}, "price": {"type": "number"},
+            "note": {"type": ["string", "null"]}, "tag": {"type": ["integer", "null"]}
+        }});
+        let mut value = json!({"id": "42", "price": " 1.5", "note": "None", "tag": "None"});
+        assert_eq!(coerce_to_schema(&mut value, &schema).len(), 3);
+        assert_eq!(
+            value,
+            json!({"id": 42, "price": 1.5, "note": "None", "tag": null})
+        );
+    }
+}
@@ -1,2 +1,10 @@
 pub mod command_line;
+pub mod context_window;
+pub mod evaluation;
 pub mod general;
+pub mod json_schema;
+pub mod lenient_decoding;
+pub mod output_extraction;
+pub mod prompt_injection;
+pub mod prompt_templates;
+pub mod structured_output;
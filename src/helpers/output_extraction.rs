@@ -0,0 +1,204 @@
+use super::command_line::PrintCommand;
+use serde_json::Value;
+
+// Payload expected in an LLM reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    JsonObject,
+    JsonArray,
+    CodeBlock,
+}
+
+// Payload pulled out of a reply, with the text around it that was dropped
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extracted {
+    pub payload: String,
+    pub stripped: Vec<String>,
+}
+
+impl Extracted {
+    // Text around the payload, trimmed - fences and commentary
+    fn new(text: &str, start: usize, end: usize) -> Self {
+        Self {
+            payload: text[start..end].trim().to_string(),
+            stripped: [&text[..start], &text[end..]]
+                .iter()
+                .map(|part| part.trim())
+                .filter(|part| !part.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    // Nothing found - the whole reply is the payload
+    fn whole(text: &str) -> Self {
+        Self {
+            payload: text.trim().to_string(),
+            stripped: vec![],
+        }
+    }
+
+    // Print what was stripped for the calling agent and return the payload
+    pub fn logged(self, agent_position: &str, ai_function: &str) -> String {
+        for part in &self.stripped {
+            let snippet: String = part.chars().take(80).collect();
+            PrintCommand::Issue.print_agent_message(
+                agent_position,
+                &format!(
+                    "Stripped {} chars from {} output: {:?}",
+                    part.chars().count(),
+                    ai_function,
+                    snippet
+                ),
+            );
+        }
+        self.payload
+    }
+}
+
+// Pull the payload of the given kind out of a reply
+// JSON is the first complete object or array that parses - fences and commentary around it are dropped
+// Code is the longest fenced block, or the whole reply when it has no fences
+pub fn extract_output(text: &str, kind: OutputKind) -> Extracted {
+    let span: Option<(usize, usize)> = match kind {
+        OutputKind::JsonObject => find_json(text, '{'),
+        OutputKind::JsonArray => find_json(text, '['),
+        OutputKind::CodeBlock => find_code_block(text),
+    };
+
+    match span {
+        Some((start, end)) => Extracted::new(text, start, end),
+        None => Extracted::whole(text),
+    }
+}
+
+// JSON object or array, whichever starts first
+pub fn extract_json(text: &str) -> Extracted {
+    let span: Option<(usize, usize)> = [find_json(text, '{'), find_json(text, '[')]
+        .into_iter()
+        .flatten()
+        .min_by_key(|(start, _)| *start);
+
+    match span {
+        Some((start, end)) => Extracted::new(text, start, end),
+        None => Extracted::whole(text),
+    }
+}
+
+// Byte span of the first balanced value opened by `open` that is valid JSON
+fn find_json(text: &str, open: char) -> Option<(usize, usize)> {
+    text.match_indices(open).find_map(|(start, _)| {
+        let end: usize = start + balanced_len(&text[start..])?;
+        serde_json::from_str::<Value>(&text[start..end])
+            .is_ok()
+            .then_some((start, end))
+    })
+}
+
+// Length up to the bracket closing the one `text` starts with - brackets in strings are skipped
+fn balanced_len(text: &str) -> Option<usize> {
+    let mut depth: usize = 0;
+    let mut in_string: bool = false;
+    let mut escaped: bool = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+// Byte span of the contents of the longest ``` fenced block
+// An unclosed fence (a cut off reply) runs to the end
+fn find_code_block(text: &str) -> Option<(usize, usize)> {
+    let mut blocks: Vec<(usize, usize)> = vec![];
+    let mut open: Option<usize> = None;
+    let mut offset: usize = 0;
+
+    for line in text.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            match open.take() {
+                Some(start) => blocks.push((start, offset)),
+                None => open = Some(offset + line.len()),
+            }
+        }
+        offset += line.len();
+    }
+    if let Some(start) = open {
+        blocks.push((start, text.len()));
+    }
+
+    blocks.into_iter().max_by_key(|(start, end)| end - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_extracts_json_from_commentary() {
+        let reply = "Sure! Here is the scope:\n```json\n{\"is_crud_required\": true, \"note\": \"uses {braces}\"}\n```\nLet me know if you need more.";
+
+        let extracted = extract_output(reply, OutputKind::JsonObject);
+        assert_eq!(
+            extracted.payload,
+            "{\"is_crud_required\": true, \"note\": \"uses {braces}\"}"
+        );
+        assert_eq!(
+            extracted.stripped,
+            vec![
+                "Sure! Here is the scope:\n```json",
+                "```\nLet me know if you need more."
+            ]
+        );
+
+        // Brackets in commentary are not mistaken for the payload
+        let reply = "The urls [as requested]:\n[\"https://api.binance.com\"]";
+        assert_eq!(extract_json(reply).payload, "[\"https://api.binance.com\"]");
+        assert_eq!(
+            extract_output(reply, OutputKind::JsonArray).stripped,
+            vec!["The urls [as requested]:"]
+        );
+
+        // Clean output is left alone
+        let extracted = extract_json("[]");
+        assert_eq!(extracted.payload, "[]");
+        assert!(extracted.stripped.is_empty());
+    }
+
+    #[test]
+    fn tests_extracts_code_block() {
+        let reply = "Here is the code:\n```rust\nfn main() {\n    println!(\"```\");\n}\n```\nThis starts the server.";
+        let extracted = extract_output(reply, OutputKind::CodeBlock);
+        assert_eq!(extracted.payload, "fn main() {\n    println!(\"```\");\n}");
+        assert_eq!(
+            extracted.stripped,
+            vec!["Here is the code:\n```rust", "```\nThis starts the server."]
+        );
+
+        // No fences - the reply is the code
+        let extracted = extract_output("fn main() {}\n", OutputKind::CodeBlock);
+        assert_eq!(extracted.payload, "fn main() {}");
+        assert!(extracted.stripped.is_empty());
+    }
+}
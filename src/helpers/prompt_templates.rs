@@ -0,0 +1,172 @@
+use crate::ai_functions::aifunc_architect::{print_project_scope, print_site_urls};
+use crate::ai_functions::aifunc_backend::{
+    print_backend_webserver_code, print_fixed_code, print_improved_webserver_code,
+    print_rest_api_endpoints,
+};
+use crate::ai_functions::aifunc_managing::convert_user_input_to_goal;
+use crate::errors::AutoGptError;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub type AiFunction = for<'a> fn(&'a str) -> &'static str;
+
+// Replaced by the text of the ai function and by a reference to its input
+// The input itself is sent in a separate message (see helpers::prompt_injection)
+pub const FUNCTION_PLACEHOLDER: &str = "{{function}}";
+pub const INPUT_PLACEHOLDER: &str = "{{input}}";
+
+// Extend the ai function to encourage only printing the output
+// Every ai function shares this wrapper - its own instructions are the text of the function,
+// filled in for {{function}}
+pub const BUILT_IN_TEMPLATE: &str = "FUNCTION: {{function}}
+      INSTRUCTION: You are a function pointer. You ONLY print the results of functions.
+      Nothing else. No commentary. The input to the function is {{input}}.
+      Print out what the function will return.";
+
+// ai functions whose prompts ship as built-in templates
+pub const AI_FUNCTIONS: [(&str, AiFunction); 7] = [
+    (
+        get_function_string!(convert_user_input_to_goal),
+        convert_user_input_to_goal,
+    ),
+    (
+        get_function_string!(print_project_scope),
+        print_project_scope,
+    ),
+    (get_function_string!(print_site_urls), print_site_urls),
+    (
+        get_function_string!(print_backend_webserver_code),
+        print_backend_webserver_code,
+    ),
+    (
+        get_function_string!(print_improved_webserver_code),
+        print_improved_webserver_code,
+    ),
+    (get_function_string!(print_fixed_code), print_fixed_code),
+    (
+        get_function_string!(print_rest_api_endpoints),
+        print_rest_api_endpoints,
+    ),
+];
+
+// Name of an ai function, as get_function_string! gives it - the text of an #[ai_function]
+// starts with its signature, "pub fn <name>(..." (possibly wrapped after fn)
+pub fn ai_function_name(function_pass: AiFunction) -> &'static str {
+    let name: &'static str = function_pass("")
+        .split_whitespace()
+        .skip_while(|word| *word != "fn")
+        .nth(1)
+        .unwrap_or_default();
+    let end: usize = name
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(name.len());
+    &name[..end]
+}
+
+// Fill in the placeholders - the function first, so input that happens to contain
+// {{function}} is left as it is
+pub fn fill_template(template: &str, function: &str, input: &str) -> String {
+    template
+        .replace(FUNCTION_PLACEHOLDER, function)
+        .replace(INPUT_PLACEHOLDER, input)
+}
+
+fn template_path(dir: &Path, ai_function: &str) -> PathBuf {
+    dir.join(format!("{}.txt", ai_function))
+}
+
+// Template for an ai function - <dir>/<ai_function>.txt when present, else the built-in one
+// Override files are read on every call, so prompt edits apply without a rebuild or restart
+pub fn load_template(dir: &Path, ai_function: &str) -> Result<String, AutoGptError> {
+    let path: PathBuf = template_path(dir, ai_function);
+
+    match fs::read_to_string(&path) {
+        Ok(template) => Ok(template),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BUILT_IN_TEMPLATE.to_string()),
+        Err(source) => Err(AutoGptError::Io {
+            path: path.display().to_string(),
+            source,
+        }),
+    }
+}
+
+// Write the built-in templates to dir as a starting point for overrides
+// Each file is the shared wrapper with the text of its function filled in and {{input}} kept,
+// so an override replaces the whole prompt of that function, wrapper and instructions alike
+// A file that keeps {{function}} instead only rewraps the function text
+// Existing files are left alone - returns the files written
+pub fn export_templates(dir: &Path) -> Result<Vec<PathBuf>, AutoGptError> {
+    let io_error = |path: &Path| {
+        let path: String = path.display().to_string();
+        move |source: io::Error| AutoGptError::Io { path, source }
+    };
+
+    fs::create_dir_all(dir).map_err(io_error(dir))?;
+
+    let mut written: Vec<PathBuf> = vec![];
+    for (ai_function, function_pass) in AI_FUNCTIONS {
+        let path: PathBuf = template_path(dir, ai_function);
+        if path.exists() {
+            continue;
+        }
+
+        let template: String = fill_template(
+            BUILT_IN_TEMPLATE,
+            function_pass(INPUT_PLACEHOLDER),
+            INPUT_PLACEHOLDER,
+        );
+        fs::write(&path, template).map_err(io_error(&path))?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn tests_ai_function_name() {
+        for (name, function_pass) in AI_FUNCTIONS {
+            assert_eq!(ai_function_name(function_pass), name);
+        }
+    }
+
+    #[test]
+    fn tests_override_and_export_templates() {
+        let dir: PathBuf = env::temp_dir().join(format!("auto_gpt_prompts_{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+
+        // Nothing overridden - built-in template
+        assert_eq!(
+            load_template(&dir, "print_project_scope").unwrap(),
+            BUILT_IN_TEMPLATE
+        );
+
+        let written: Vec<PathBuf> = export_templates(&dir).unwrap();
+        assert_eq!(written.len(), AI_FUNCTIONS.len());
+        let exported: String = load_template(&dir, "print_project_scope").unwrap();
+        assert!(exported.contains("Converts user request into JSON response"));
+        assert!(exported.contains(INPUT_PLACEHOLDER));
+        assert!(!exported.contains(FUNCTION_PLACEHOLDER));
+
+        // Edited override is picked up on the next load, existing files are not exported over
+        fs::write(
+            dir.join("print_project_scope.txt"),
+            "List what {{input}} needs.",
+        )
+        .unwrap();
+        assert!(export_templates(&dir).unwrap().is_empty());
+        let template: String = load_template(&dir, "print_project_scope").unwrap();
+        assert_eq!(
+            fill_template(&template, "", "a todo app"),
+            "List what a todo app needs."
+        );
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
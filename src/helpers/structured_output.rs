@@ -0,0 +1,219 @@
+use crate::apis::llm_provider::StructuredOutput;
+use crate::models::general::llm::{
+    APIMessage, ChatCompletion, FunctionDefinition, Message, ResponseFormat, Tool,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+// JSON mode and tool arguments must be objects - outputs such as url lists are wrapped
+// in {"result": ...}
+const RESULT_FIELD: &str = "result";
+
+// Ask the provider for JSON matching the schema of the decoded type
+// (see helpers::json_schema) - valid JSON by construction where the provider supports it
+pub fn request_structured_output(
+    chat_completion: &mut ChatCompletion,
+    structured_output: StructuredOutput,
+    ai_function: &str,
+    schema: &Value,
+) {
+    chat_completion.meta.output_schema = Some(schema.clone());
+    chat_completion
+        .messages
+        .extend(instruction_message(structured_output, schema));
+
+    match structured_output {
+        StructuredOutput::Prompt => {}
+        StructuredOutput::JsonMode => {
+            chat_completion.response_format = Some(ResponseFormat::json_object());
+        }
+        StructuredOutput::Tools => {
+            let name: String = tool_name(ai_function);
+            chat_completion.tools = Some(vec![Tool {
+                tool_type: "function".to_string(),
+                function: FunctionDefinition {
+                    name: name.clone(),
+                    description: "Return the output of the function".to_string(),
+                    parameters: json!({
+                        "type": "object",
+                        "properties": { RESULT_FIELD: schema },
+                        "required": [RESULT_FIELD]
+                    }),
+                },
+            }]);
+            chat_completion.tool_choice = Some(json!({
+                "type": "function",
+                "function": { "name": name }
+            }));
+        }
+    }
+}
+
+// Schema instruction sent with the prompt - tools carry the schema themselves
+fn instruction_message(structured_output: StructuredOutput, schema: &Value) -> Option<Message> {
+    let content: String = match structured_output {
+        StructuredOutput::Prompt => format!(
+            "Respond only with JSON matching this JSON Schema: {}",
+            schema
+        ),
+        StructuredOutput::JsonMode => format!(
+            "Respond with a JSON object of the form {{\"{}\": <function output>}} \
+             where the function output matches this JSON Schema: {}",
+            RESULT_FIELD, schema
+        ),
+        StructuredOutput::Tools => return None,
+    };
+
+    Some(Message {
+        role: "system".to_string(),
+        content,
+    })
+}
+
+// Ask a request shaped for one kind of structured output for another, for a provider
+// that supports a different one - the schema instruction stays where it was
+// Requests without structured output only lose the fields the provider may not support
+pub fn reshape_structured_output(
+    chat_completion: &mut ChatCompletion,
+    from: StructuredOutput,
+    to: StructuredOutput,
+) {
+    chat_completion.response_format = None;
+    chat_completion.tools = None;
+    chat_completion.tool_choice = None;
+
+    let Some(schema) = chat_completion.meta.output_schema.clone() else {
+        return;
+    };
+
+    let position: Option<usize> = instruction_message(from, &schema)
+        .and_then(|message| chat_completion.messages.iter().position(|m| *m == message));
+    if let Some(i) = position {
+        chat_completion.messages.remove(i);
+    }
+
+    let ai_function: String = chat_completion.meta.ai_function.clone();
+    request_structured_output(chat_completion, to, &ai_function, &schema);
+
+    if let (Some(i), Some(_)) = (position, instruction_message(to, &schema)) {
+        let instruction: Option<Message> = chat_completion.messages.pop();
+        chat_completion.messages.splice(i..i, instruction);
+    }
+}
+
+// Tool names only allow letters, digits, _ and - (up to 64 characters)
+fn tool_name(ai_function: &str) -> String {
+    ai_function
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(64)
+        .collect()
+}
+
+// Raw JSON text of the reply - tool call arguments, or the content when the model did not call
+pub fn structured_output_text(message: APIMessage, structured_output: StructuredOutput) -> String {
+    if structured_output == StructuredOutput::Tools {
+        if let Some(call) = message
+            .tool_calls
+            .and_then(|calls| calls.into_iter().next())
+        {
+            return call.function.arguments;
+        }
+    }
+
+    message.content
+}
+
+// Decode the reply, unwrapping {"result": ...} when it was requested
+pub fn decode_structured_output<T: DeserializeOwned>(
+    text: &str,
+    structured_output: StructuredOutput,
+) -> Result<T, serde_json::Error> {
+    if structured_output == StructuredOutput::Prompt {
+        return serde_json::from_str(text);
+    }
+
+    match serde_json::from_str::<Value>(text)? {
+        Value::Object(mut object) if object.len() == 1 && object.contains_key(RESULT_FIELD) => {
+            serde_json::from_value(object.remove(RESULT_FIELD).unwrap_or_default())
+        }
+        value => serde_json::from_value(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::general::llm::{APIFunctionCall, APIToolCall};
+
+    #[test]
+    fn tests_tools_request_and_arguments() {
+        let mut chat_completion = ChatCompletion::default();
+        let schema: Value = json!({"type": "array", "items": {"type": "string"}});
+        request_structured_output(
+            &mut chat_completion,
+            StructuredOutput::Tools,
+            get_function_string!(print_site_urls),
+            &schema,
+        );
+
+        let tools = chat_completion.tools.unwrap();
+        assert_eq!(tools[0].function.name, "print_site_urls");
+        assert_eq!(
+            tools[0].function.parameters["properties"][RESULT_FIELD],
+            schema
+        );
+        assert_eq!(
+            chat_completion.tool_choice.unwrap()["function"]["name"],
+            "print_site_urls"
+        );
+        assert_eq!(
+            tool_name("Testing decoded request"),
+            "Testing_decoded_request"
+        );
+
+        let message = APIMessage {
+            content: String::new(),
+            tool_calls: Some(vec![APIToolCall {
+                function: APIFunctionCall {
+                    name: "print_site_urls".to_string(),
+                    arguments: r#"{"result": ["https://api.binance.com"]}"#.to_string(),
+                },
+            }]),
+        };
+        let text = structured_output_text(message, StructuredOutput::Tools);
+        let urls: Vec<String> = decode_structured_output(&text, StructuredOutput::Tools).unwrap();
+        assert_eq!(urls, vec!["https://api.binance.com"]);
+    }
+
+    #[test]
+    fn tests_json_mode_request_and_unwrap() {
+        let mut chat_completion = ChatCompletion::default();
+        request_structured_output(
+            &mut chat_completion,
+            StructuredOutput::JsonMode,
+            get_function_string!(print_project_scope),
+            &json!({"type": "object"}),
+        );
+
+        assert_eq!(
+            chat_completion.response_format,
+            Some(ResponseFormat::json_object())
+        );
+        assert!(chat_completion.messages[0]
+            .content
+            .contains(r#"JSON Schema: {"type":"object"}"#));
+
+        // Unwrapped output is accepted as well
+        let scope: Value =
+            decode_structured_output(r#"{"is_crud_required": true}"#, StructuredOutput::JsonMode)
+                .unwrap();
+        assert_eq!(scope["is_crud_required"], true);
+    }
+}
@@ -8,13 +8,75 @@ macro_rules! get_function_string {
 #[macro_use]
 mod ai_functions;
 mod apis;
+mod errors;
 mod helpers;
 mod models;
 
-use helpers::command_line::get_user_response;
+use apis::llm_provider::{provider_from_env, LlmProvider};
+use apis::transcript::TranscriptProvider;
+use errors::AutoGptError;
+use helpers::command_line::{
+    get_user_response, run_eval_command, run_prompts_command, run_transcript_command, PrintCommand,
+};
+use models::agents_manager::managing_agent::ManagingAgent;
+use models::general::llm_config::LlmConfig;
+
+use std::env;
+use std::sync::Arc;
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // Inspect a transcript of an earlier run instead of building
+    if args.first().map(String::as_str) == Some("transcript") {
+        if let Err(e) = run_transcript_command(&args[1..]) {
+            PrintCommand::Issue.print_agent_message("Auto GPT", e.to_string().as_str());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Write the built-in prompt templates so they can be edited without a rebuild
+    if args.first().map(String::as_str) == Some("prompts") {
+        if let Err(e) = run_prompts_command(&args[1..]) {
+            PrintCommand::Issue.print_agent_message("Auto GPT", e.to_string().as_str());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Score prompts against a golden dataset
+    if args.first().map(String::as_str) == Some("eval") {
+        if let Err(e) = run_eval_command(&args[1..]).await {
+            PrintCommand::Issue.print_agent_message("Auto GPT", e.to_string().as_str());
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // --no-cache forces fresh LLM responses even when LLM_CACHE is enabled
+    let no_cache: bool = args.iter().any(|arg| arg == "--no-cache");
 
-fn main() {
     let user_req: String = get_user_response("What webserver are we building today?");
 
-    dbg!(user_req);
+    if let Err(e) = run(user_req, no_cache).await {
+        PrintCommand::Issue.print_agent_message("Auto GPT", e.to_string().as_str());
+        std::process::exit(1);
+    }
+}
+
+async fn run(user_req: String, no_cache: bool) -> Result<(), AutoGptError> {
+    // Model, sampling and fallback settings per agent and ai function
+    let llm_config: Arc<LlmConfig> = Arc::new(LlmConfig::from_env()?);
+
+    // Any OpenAI compatible endpoint - see .env.example
+    // Every call of this run is written to a JSONL transcript
+    let provider: Arc<dyn LlmProvider> =
+        TranscriptProvider::wrap_from_env(provider_from_env(no_cache, &llm_config)?)?;
+
+    let mut manage_agent: ManagingAgent =
+        ManagingAgent::new(user_req, provider, llm_config).await?;
+
+    manage_agent.execute_project().await
 }
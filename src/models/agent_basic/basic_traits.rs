@@ -1,6 +1,7 @@
 use crate::models::agent_basic::basic_agent::AgentState;
 use crate::models::general::llm::Message;
 
+#[allow(dead_code)]
 pub trait BasicTraits {
     fn new(objective: String, position: String) -> Self;
     fn update_state(&mut self, new_state: AgentState);
@@ -1,64 +1,96 @@
 use crate::ai_functions::aifunc_architect::{print_project_scope, print_site_urls};
+use crate::apis::llm_provider::LlmProvider;
+use crate::errors::AutoGptError;
 use crate::helpers::command_line::PrintCommand;
-use crate::helpers::general::{ai_task_request_decoded, check_status_code};
+use crate::helpers::general::{
+    ai_task_request_decoded_sampled, ai_task_request_decoded_with_memory, check_status_code,
+};
 use crate::models::agent_basic::basic_agent::{AgentState, BasicAgent};
 use crate::models::agent_basic::basic_traits::BasicTraits;
 use crate::models::agents::agent_traits::{FactSheet, ProjectScope, SpecialFunctions};
+use crate::models::general::llm_config::LlmConfig;
 
 use async_trait::async_trait;
 use reqwest::Client;
+use std::sync::Arc;
 use std::time::Duration;
 
+// Position of the agent - LlmConfig settings and eval cases refer to it
+pub const SOLUTIONS_ARCHITECT: &str = "Solutions Architect";
+
 // Solutions architect
 #[derive(Debug)]
 pub struct AgentSolutionArchitect {
     attributes: BasicAgent,
+    provider: Arc<dyn LlmProvider>,
+    llm_config: Arc<LlmConfig>,
 }
 
 impl AgentSolutionArchitect {
     #[allow(dead_code)]
-    pub fn new() -> Self {
+    pub fn new(provider: Arc<dyn LlmProvider>, llm_config: Arc<LlmConfig>) -> Self {
         let attributes = BasicAgent {
             objective: "Gathers information and design solutions for website development"
                 .to_string(),
-            position: "Solutions Architect".to_string(),
+            position: SOLUTIONS_ARCHITECT.to_string(),
             state: AgentState::Discovery,
             memory: vec![],
         };
 
-        Self { attributes }
+        Self {
+            attributes,
+            provider,
+            llm_config,
+        }
+    }
+
+    fn agent_error(&self, message: String) -> AutoGptError {
+        AutoGptError::Agent {
+            position: self.attributes.position.clone(),
+            message,
+        }
     }
 
     // Retrieve Project Scope
-    async fn call_project_scope(&mut self, factsheet: &mut FactSheet) -> ProjectScope {
-        let ai_response: ProjectScope = ai_task_request_decoded::<ProjectScope>(
+    async fn call_project_scope(
+        &mut self,
+        factsheet: &mut FactSheet,
+    ) -> Result<ProjectScope, AutoGptError> {
+        // Majority of the configured samples - see LlmSettings::samples
+        let (ai_response, confidence) = ai_task_request_decoded_sampled::<ProjectScope>(
+            self.provider.as_ref(),
+            &self.llm_config,
+            &mut self.attributes.memory,
             factsheet.project_description.to_string(),
             &self.attributes.position,
-            get_function_string!(print_project_scope),
             print_project_scope,
         )
-        .await;
+        .await?;
 
         factsheet.project_scope = Some(ai_response);
+        factsheet.project_scope_confidence = Some(confidence);
         self.attributes.update_state(AgentState::Finished);
-        ai_response
+        Ok(ai_response)
     }
 
     async fn call_determine_external_urls(
         &mut self,
         factsheet: &mut FactSheet,
         msg_context: String,
-    ) {
-        let ai_response: Vec<String> = ai_task_request_decoded::<Vec<String>>(
+    ) -> Result<(), AutoGptError> {
+        let ai_response: Vec<String> = ai_task_request_decoded_with_memory::<Vec<String>>(
+            self.provider.as_ref(),
+            &self.llm_config,
+            &mut self.attributes.memory,
             msg_context,
             &self.attributes.position,
-            get_function_string!(print_site_urls),
             print_site_urls,
         )
-        .await;
+        .await?;
 
         factsheet.external_urls = Some(ai_response);
         self.attributes.update_state(AgentState::UnitTesting);
+        Ok(())
     }
 }
 
@@ -68,15 +100,12 @@ impl SpecialFunctions for AgentSolutionArchitect {
         &self.attributes
     }
 
-    async fn execute(
-        &mut self,
-        factsheet: &mut FactSheet,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn execute(&mut self, factsheet: &mut FactSheet) -> Result<(), AutoGptError> {
         // !!! WARNING - BE CAREFUL OF INFINITE LOOPS !!!
         while self.attributes.state != AgentState::Finished {
             match self.attributes.state {
                 AgentState::Discovery => {
-                    let project_scope = self.call_project_scope(factsheet).await;
+                    let project_scope = self.call_project_scope(factsheet).await?;
 
                     // Confirm if external urls
                     if project_scope.is_external_urls_required {
@@ -84,7 +113,7 @@ impl SpecialFunctions for AgentSolutionArchitect {
                             factsheet,
                             factsheet.project_description.clone(),
                         )
-                        .await;
+                        .await?;
                         self.attributes.update_state(AgentState::UnitTesting);
                     }
                 }
@@ -94,13 +123,12 @@ impl SpecialFunctions for AgentSolutionArchitect {
                     let client: Client = Client::builder()
                         .timeout(Duration::from_secs(5))
                         .build()
-                        .unwrap();
+                        .map_err(|e| self.agent_error(format!("Failed to build client: {}", e)))?;
 
                     // Defining urls to check
-                    let urls: &Vec<String> = factsheet
-                        .external_urls
-                        .as_ref()
-                        .expect("No URL object on factsheet");
+                    let urls: &Vec<String> = factsheet.external_urls.as_ref().ok_or_else(|| {
+                        self.agent_error("No URL object on factsheet".to_string())
+                    })?;
 
                     // Find faulty urls
                     for url in urls {
@@ -123,10 +151,7 @@ impl SpecialFunctions for AgentSolutionArchitect {
 
                     // Exclude any faulty urls
                     if !exclude_urls.is_empty() {
-                        let new_urls: Vec<String> = factsheet
-                            .external_urls
-                            .as_ref()
-                            .unwrap()
+                        let new_urls: Vec<String> = urls
                             .iter()
                             .filter(|url| !exclude_urls.contains(url))
                             .cloned()
@@ -150,17 +175,37 @@ impl SpecialFunctions for AgentSolutionArchitect {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::apis::fake_provider::FakeProvider;
+    use crate::apis::mock_server::spawn_mock_server;
 
     #[tokio::test]
     async fn tests_solution_architect() {
-        let mut agent = AgentSolutionArchitect::new();
+        // The url check hits a local server - the second url is excluded as broken
+        let (base_url, server) =
+            spawn_mock_server(vec![(200, "{}".to_string()), (404, "{}".to_string())]).await;
+        let urls: String =
+            serde_json::to_string(&[format!("{}/latest", base_url), format!("{}/gone", base_url)])
+                .unwrap();
+        let provider = Arc::new(FakeProvider::new(vec![
+            r#"{
+                "is_crud_required": false,
+                "is_user_login_and_logout": true,
+                "is_external_urls_required": true
+            }"#,
+            &urls,
+        ]));
+
+        let mut agent =
+            AgentSolutionArchitect::new(provider.clone(), Arc::new(LlmConfig::default()));
 
         let mut factsheet = FactSheet {
             project_description: "Build a full stack website with user login and logout that shows latest Forex prices".to_string(),
             project_scope: None,
             external_urls: None,
             backend_code: None,
-            api_endpoint_schema: None
+            api_endpoint_schema: None,
+            project_scope_confidence: None,
+            usage_summary: None,
         };
 
         agent
@@ -169,8 +214,11 @@ mod tests {
             .expect("Unable to execute Solution Architect Agent");
 
         assert!(factsheet.project_scope.is_some());
-        assert!(factsheet.external_urls.is_some());
-
-        dbg!(factsheet);
+        assert_eq!(
+            factsheet.external_urls,
+            Some(vec![format!("{}/latest", base_url)])
+        );
+        assert_eq!(provider.requests.lock().unwrap().len(), 2);
+        assert_eq!(server.await.unwrap().len(), 2);
     }
 }
@@ -2,114 +2,172 @@ use crate::ai_functions::aifunc_backend::{
     print_backend_webserver_code, print_fixed_code, print_improved_webserver_code,
     print_rest_api_endpoints,
 };
+use crate::apis::llm_provider::LlmProvider;
+use crate::errors::AutoGptError;
 use crate::helpers::general::{
-    ai_task_request, read_code_template_contents, read_exec_main_contents, save_backend_code,
+    ai_task_request_decoded_with_memory, ai_task_request_with_memory, read_code_template_contents,
+    read_exec_main_contents, save_backend_code, ProjectPaths,
 };
+use crate::helpers::output_extraction::{extract_output, OutputKind};
 use crate::models::agent_basic::basic_agent::{AgentState, BasicAgent};
 use crate::models::agent_basic::basic_traits::BasicTraits;
-use crate::models::agents::agent_traits::{FactSheet, SpecialFunctions};
+use crate::models::agents::agent_traits::{FactSheet, RouteObject, SpecialFunctions};
+use crate::models::general::llm_config::LlmConfig;
 
 use async_trait::async_trait;
+use std::sync::Arc;
+
+// Position the backend developer is configured under
+pub const BACKEND_DEVELOPER: &str = "Backend Developer";
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct AgentBackendDeveloper {
     attributes: BasicAgent,
+    provider: Arc<dyn LlmProvider>,
+    llm_config: Arc<LlmConfig>,
+    paths: ProjectPaths,
     bug_errors: Option<String>,
     bug_count: u8,
 }
 
 impl AgentBackendDeveloper {
     #[allow(dead_code)]
-    pub fn new() -> Self {
+    pub fn new(provider: Arc<dyn LlmProvider>, llm_config: Arc<LlmConfig>) -> Self {
         let attributes = BasicAgent {
             objective: "Develops backend code for webserver and json database".to_string(),
-            position: "Backend Developer".to_string(),
+            position: BACKEND_DEVELOPER.to_string(),
             state: AgentState::Discovery,
             memory: vec![],
         };
 
         Self {
             attributes,
+            provider,
+            llm_config,
+            paths: ProjectPaths::from_env(),
             bug_errors: None,
             bug_count: 0,
         }
     }
 
+    // Read the template from and write the code to other files than configured
     #[allow(dead_code)]
-    async fn call_initial_backend_code(&mut self, factsheet: &mut FactSheet) {
-        let code_template_str = read_code_template_contents();
+    pub fn with_paths(mut self, paths: ProjectPaths) -> Self {
+        self.paths = paths;
+        self
+    }
+
+    #[allow(dead_code)]
+    async fn call_initial_backend_code(
+        &mut self,
+        factsheet: &mut FactSheet,
+    ) -> Result<(), AutoGptError> {
+        let code_template_str = read_code_template_contents(&self.paths)?;
 
         // Concatenate Instruction
+        // Code goes in fences on their own lines so it can be truncated to fit the context window
         let msg_context = format!(
-            "CODE TEMPLATE: {} \n PROJECT_DESCRIPTIOM: {} \n",
+            "CODE TEMPLATE:\n```rust\n{}\n```\nPROJECT_DESCRIPTIOM: {}\n",
             code_template_str, factsheet.project_description
         );
 
-        dbg!(msg_context.clone());
-
-        let ai_response: String = ai_task_request(
+        let ai_response: String = ai_task_request_with_memory(
+            self.provider.as_ref(),
+            &self.llm_config,
+            &mut self.attributes.memory,
             msg_context,
             &self.attributes.position,
-            get_function_string!(print_backend_webserver_code),
             print_backend_webserver_code,
         )
-        .await;
+        .await?;
 
-        save_backend_code(&ai_response);
-        factsheet.backend_code = Some(ai_response);
+        // Only the code is saved - fences and commentary are dropped
+        let code: String = extract_output(&ai_response, OutputKind::CodeBlock).logged(
+            &self.attributes.position,
+            get_function_string!(print_backend_webserver_code),
+        );
+        save_backend_code(&self.paths, &code)?;
+        factsheet.backend_code = Some(code);
+        Ok(())
     }
 
     #[allow(dead_code)]
-    async fn call_improved_backend_code(&mut self, factsheet: &mut FactSheet) {
+    async fn call_improved_backend_code(
+        &mut self,
+        factsheet: &mut FactSheet,
+    ) -> Result<(), AutoGptError> {
+        // The code is sent once, fenced, rather than inside the factsheet
+        let project: FactSheet = FactSheet {
+            backend_code: None,
+            ..factsheet.clone()
+        };
         let msg_context = format!(
-            "CODE TEMPLATE: {:?} \n PROJECT_DESCRIPTIOM: {:?} \n",
-            factsheet.backend_code, factsheet
+            "CODE TEMPLATE:\n```rust\n{}\n```\nPROJECT_DESCRIPTIOM: {:?}\n",
+            factsheet.backend_code.as_deref().unwrap_or_default(),
+            project
         );
 
-        let ai_response: String = ai_task_request(
+        let ai_response: String = ai_task_request_with_memory(
+            self.provider.as_ref(),
+            &self.llm_config,
+            &mut self.attributes.memory,
             msg_context,
             &self.attributes.position,
-            get_function_string!(print_improved_webserver_code),
             print_improved_webserver_code,
         )
-        .await;
+        .await?;
 
-        save_backend_code(&ai_response);
-        factsheet.backend_code = Some(ai_response);
+        let code: String = extract_output(&ai_response, OutputKind::CodeBlock).logged(
+            &self.attributes.position,
+            get_function_string!(print_improved_webserver_code),
+        );
+        save_backend_code(&self.paths, &code)?;
+        factsheet.backend_code = Some(code);
+        Ok(())
     }
 
     #[allow(dead_code)]
-    async fn call_fix_code_bugs(&mut self, factsheet: &mut FactSheet) {
+    async fn call_fix_code_bugs(&mut self, factsheet: &mut FactSheet) -> Result<(), AutoGptError> {
         let msg_context = format!(
-            "BROKEN_CODE: {:?} \n ERROR_BUGS: {:?} \n
+            "BROKEN_CODE:\n```rust\n{}\n```\nERROR_BUGS: {:?} \n
             THIS FUNCTION ONLY OUTPUTS CODE. JUST OUTPUT THE CODE.",
-            factsheet.backend_code, self.bug_errors
+            factsheet.backend_code.as_deref().unwrap_or_default(),
+            self.bug_errors
         );
 
-        let ai_response = ai_task_request(
+        let ai_response = ai_task_request_with_memory(
+            self.provider.as_ref(),
+            &self.llm_config,
+            &mut self.attributes.memory,
             msg_context,
             &self.attributes.position,
-            get_function_string!(print_fixed_code),
             print_fixed_code,
         )
-        .await;
+        .await?;
 
-        save_backend_code(&ai_response);
-        factsheet.backend_code = Some(ai_response);
+        let code: String = extract_output(&ai_response, OutputKind::CodeBlock).logged(
+            &self.attributes.position,
+            get_function_string!(print_fixed_code),
+        );
+        save_backend_code(&self.paths, &code)?;
+        factsheet.backend_code = Some(code);
+        Ok(())
     }
 
     #[allow(dead_code)]
-    async fn call_extract_rest_api_endpoints(&self) -> String {
-        let backend_code = read_exec_main_contents();
+    async fn call_extract_rest_api_endpoints(&mut self) -> Result<Vec<RouteObject>, AutoGptError> {
+        let backend_code = read_exec_main_contents(&self.paths)?;
 
         // Structure message context
-        let msg_context = format!("CODE_INPUT: {:?}", backend_code);
+        let msg_context = format!("CODE_INPUT:\n```rust\n{}\n```", backend_code);
 
-        ai_task_request(
+        ai_task_request_decoded_with_memory::<Vec<RouteObject>>(
+            self.provider.as_ref(),
+            &self.llm_config,
+            &mut self.attributes.memory,
             msg_context,
             &self.attributes.position,
-            get_function_string!(print_rest_api_endpoints),
             print_rest_api_endpoints,
         )
         .await
@@ -122,22 +180,19 @@ impl SpecialFunctions for AgentBackendDeveloper {
         &self.attributes
     }
 
-    async fn execute(
-        &mut self,
-        factsheet: &mut FactSheet,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    async fn execute(&mut self, factsheet: &mut FactSheet) -> Result<(), AutoGptError> {
         while self.attributes.state != AgentState::Finished {
             match &self.attributes.state {
                 AgentState::Discovery => {
-                    self.call_initial_backend_code(factsheet).await;
+                    self.call_initial_backend_code(factsheet).await?;
                     self.attributes.update_state(AgentState::Working);
                     continue;
                 }
                 AgentState::Working => {
                     if self.bug_count == 0 {
-                        self.call_improved_backend_code(factsheet).await;
+                        self.call_improved_backend_code(factsheet).await?;
                     } else {
-                        self.call_fix_code_bugs(factsheet).await;
+                        self.call_fix_code_bugs(factsheet).await?;
                     }
                     self.attributes.update_state(AgentState::UnitTesting);
                     continue;
@@ -154,10 +209,29 @@ impl SpecialFunctions for AgentBackendDeveloper {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::apis::fake_provider::FakeProvider;
+    use std::fs;
 
     #[tokio::test]
     async fn tests_writing_backend_code() {
-        let mut agent = AgentBackendDeveloper::new();
+        let provider = Arc::new(FakeProvider::new(vec![
+            "fn main() { println!(\"initial\"); }",
+            "fn main() { println!(\"improved\"); }",
+        ]));
+
+        // A web-template project of its own instead of the configured one
+        let dir = std::env::temp_dir().join(format!("auto_gpt_backend_{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("src/code_template.rs"),
+            "fn main() { println!(\"template\"); }",
+        )
+        .unwrap();
+        let paths = ProjectPaths::new(&dir, dir.join("api_schema.json"));
+
+        let mut agent =
+            AgentBackendDeveloper::new(provider.clone(), Arc::new(LlmConfig::default()))
+                .with_paths(paths.clone());
 
         let factsheet_str = r#"
             {
@@ -181,5 +255,20 @@ mod tests {
             .execute(&mut factsheet)
             .await
             .expect("Failed to execute Backend Developer Agent");
+
+        // The template went into the prompt and the improved code was saved
+        assert!(provider.requests.lock().unwrap()[0].messages[1]
+            .content
+            .contains("println!(\"template\")"));
+        assert_eq!(
+            fs::read_to_string(&paths.exec_main).unwrap(),
+            "fn main() { println!(\"improved\"); }"
+        );
+        assert_eq!(
+            factsheet.backend_code.as_deref(),
+            Some("fn main() { println!(\"improved\"); }")
+        );
+
+        fs::remove_dir_all(&dir).ok();
     }
 }
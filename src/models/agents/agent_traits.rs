@@ -1,21 +1,35 @@
+use crate::errors::AutoGptError;
 use crate::models::agent_basic::basic_agent::BasicAgent;
+use crate::models::general::self_consistency::Confidence;
+use crate::models::general::usage::UsageSummary;
 use async_trait::async_trait;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+// Decoded types are described to the model by their JSON Schema - the doc comments
+// on the fields become the field descriptions
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
 pub struct RouteObject {
+    /// true if the route has curly braces in it such as {symbol} or {id}
     pub is_route_dynamic: bool,
+    /// HTTP method of the endpoint, such as get or post
     pub method: String,
+    /// Fields and their types of the request body, "None" when there is none
     pub request_body: serde_json::Value,
+    /// Fields and their types of the response, "None" when there is none
     pub response: serde_json::Value,
+    /// URL path of the endpoint
     pub route: String,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, JsonSchema)]
 pub struct ProjectScope {
+    /// true if the site needs CRUD functionality
     pub is_crud_required: bool,
+    /// true if the site needs users to be able to log in and log out
     pub is_user_login_and_logout: bool,
+    /// true if the site needs to fetch data from third party providers
     pub is_external_urls_required: bool,
 }
 
@@ -26,16 +40,18 @@ pub struct FactSheet {
     pub external_urls: Option<Vec<String>>,
     pub backend_code: Option<String>,
     pub api_endpoint_schema: Option<Vec<RouteObject>>,
+    #[serde(default)]
+    pub project_scope_confidence: Option<Confidence>,
+    #[serde(default)]
+    pub usage_summary: Option<UsageSummary>,
 }
 
 #[async_trait]
 pub trait SpecialFunctions: Debug {
     // Used to that manager can get attributes from Agents
+    #[allow(dead_code)]
     fn get_attributes_from_agent(&self) -> &BasicAgent;
 
     // This function will allow agents to execute their logic
-    async fn execute(
-        &mut self,
-        factsheet: &mut FactSheet,
-    ) -> Result<(), Box<dyn std::error::Error>>;
+    async fn execute(&mut self, factsheet: &mut FactSheet) -> Result<(), AutoGptError>;
 }
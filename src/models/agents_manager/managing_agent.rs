@@ -1,23 +1,46 @@
 use crate::ai_functions::aifunc_managing::convert_user_input_to_goal;
-use crate::helpers::general::ai_task_request;
+use crate::apis::llm_provider::LlmProvider;
+use crate::apis::usage_tracker::UsageTrackingProvider;
+use crate::errors::AutoGptError;
+use crate::helpers::command_line::{print_usage_summary, PrintCommand};
+use crate::helpers::general::ai_task_request_with_memory;
 use crate::models::agent_basic::basic_agent::{AgentState, BasicAgent};
 use crate::models::agents::agent_architect::AgentSolutionArchitect;
 use crate::models::agents::agent_traits::{FactSheet, SpecialFunctions};
+use crate::models::general::llm_config::LlmConfig;
+use crate::models::general::usage::{PriceTable, UsageLedger};
+
+use std::sync::Arc;
+
+// Settings for the manager resolve under this position
+pub const PROJECT_MANAGER: &str = "Project Manager";
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct ManagingAgent {
     attributes: BasicAgent,
     factsheet: FactSheet,
+    provider: Arc<dyn LlmProvider>,
+    llm_config: Arc<LlmConfig>,
+    usage: Arc<UsageLedger>,
     agents: Vec<Box<dyn SpecialFunctions>>,
 }
 
 impl ManagingAgent {
     #[allow(dead_code)]
-    pub async fn new(usr_req: String) -> Result<Self, Box<dyn std::error::Error>> {
-        let position = "Project Manager".to_string();
-
-        let attributes = BasicAgent {
+    pub async fn new(
+        usr_req: String,
+        provider: Arc<dyn LlmProvider>,
+        llm_config: Arc<LlmConfig>,
+    ) -> Result<Self, AutoGptError> {
+        let position = PROJECT_MANAGER.to_string();
+
+        // Every call made for this project is accounted for in one ledger
+        let usage = Arc::new(UsageLedger::new(PriceTable::from_env()?));
+        let provider: Arc<dyn LlmProvider> =
+            Arc::new(UsageTrackingProvider::new(provider, usage.clone()));
+
+        let mut attributes = BasicAgent {
             objective: "Manage agents who are building an excellent website for the user"
                 .to_string(),
             position: position.clone(),
@@ -25,13 +48,15 @@ impl ManagingAgent {
             memory: vec![],
         };
 
-        let project_description = ai_task_request(
+        let project_description = ai_task_request_with_memory(
+            provider.as_ref(),
+            &llm_config,
+            &mut attributes.memory,
             usr_req,
             &position,
-            get_function_string!(convert_user_input_to_goal),
             convert_user_input_to_goal,
         )
-        .await;
+        .await?;
 
         let agents: Vec<Box<dyn SpecialFunctions>> = vec![];
 
@@ -41,11 +66,16 @@ impl ManagingAgent {
             external_urls: None,
             backend_code: None,
             api_endpoint_schema: None,
+            project_scope_confidence: None,
+            usage_summary: None,
         };
 
         Ok(Self {
             attributes,
             factsheet,
+            provider,
+            llm_config,
+            usage,
             agents,
         })
     }
@@ -57,38 +87,120 @@ impl ManagingAgent {
 
     #[allow(dead_code)]
     fn create_agents(&mut self) {
-        self.add_agent(Box::new(AgentSolutionArchitect::new()));
+        self.add_agent(Box::new(AgentSolutionArchitect::new(
+            self.provider.clone(),
+            self.llm_config.clone(),
+        )));
         // ! TODO Add BACKEND AGENT
     }
 
     #[allow(dead_code)]
-    pub async fn execute_project(&mut self) {
+    pub async fn execute_project(&mut self) -> Result<(), AutoGptError> {
         self.create_agents();
 
-        for agent in &mut self.agents {
-            let _agent_res: Result<(), Box<dyn std::error::Error>> =
-                agent.execute(&mut self.factsheet).await;
+        let mut outcome: Result<(), AutoGptError> = Ok(());
 
-            let agent_info = agent.get_attributes_from_agent();
-            dbg!(agent_info);
+        for agent in &mut self.agents {
+            let agent_res: Result<(), AutoGptError> = agent.execute(&mut self.factsheet).await;
+
+            // Report agent failures - stop when later agents would fail the same way
+            if let Err(e) = agent_res {
+                PrintCommand::Issue
+                    .print_agent_message(&self.attributes.position, e.to_string().as_str());
+
+                if e.is_fatal() {
+                    outcome = Err(e);
+                    break;
+                }
+            }
         }
+
+        // Report what the run cost - also when it was cut short
+        let usage_summary = self.usage.summary();
+        print_usage_summary(&usage_summary);
+        self.factsheet.usage_summary = Some(usage_summary);
+
+        outcome
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::apis::fake_provider::FakeProvider;
+    use crate::apis::llm_error::LlmError;
 
     #[tokio::test]
     async fn tests_managing_agent() {
+        let provider = Arc::new(FakeProvider::new(vec![
+            "build a website that fetches and tracks fitness progress including timezone information",
+            r#"{
+                "is_crud_required": true,
+                "is_user_login_and_logout": false,
+                "is_external_urls_required": false
+            }"#,
+        ]));
+
         let usr_request: &str = "need a full stack app that fetches and tracks my fitness progress. Needs to include timezone information.";
 
-        let mut managing_agent = ManagingAgent::new(usr_request.to_string())
+        let mut managing_agent = ManagingAgent::new(
+            usr_request.to_string(),
+            provider,
+            Arc::new(LlmConfig::default()),
+        )
+        .await
+        .expect("Error creating Managing Agent");
+
+        managing_agent
+            .execute_project()
             .await
-            .expect("Error creating Managing Agent");
+            .expect("Error executing project");
 
-        managing_agent.execute_project().await;
+        let usage_summary = managing_agent.factsheet.usage_summary.clone().unwrap();
+        assert_eq!(usage_summary.project.calls, 2);
+        assert_eq!(usage_summary.by_agent["Solutions Architect"].calls, 1);
+        assert!(usage_summary.by_function["convert_user_input_to_goal"].completion_tokens > 0);
 
         dbg!(managing_agent.factsheet);
     }
+
+    #[tokio::test]
+    async fn tests_managing_agent_reacts_to_agent_errors() {
+        // Undecodable scope, repairs included - reported, the run carries on
+        let provider = Arc::new(FakeProvider::new(vec![
+            "build a website that lists todos",
+            "I think the site needs CRUD but no login.",
+            "CRUD, no login.",
+            "It needs CRUD.",
+        ]));
+        let mut managing_agent = ManagingAgent::new(
+            "todo app".to_string(),
+            provider,
+            Arc::new(LlmConfig::default()),
+        )
+        .await
+        .unwrap();
+        assert!(managing_agent.execute_project().await.is_ok());
+        assert!(managing_agent.factsheet.project_scope.is_none());
+
+        // Provider failure - the run stops with the error
+        let provider = Arc::new(FakeProvider::with_results(vec![
+            Ok("build a website that lists todos".to_string()),
+            Err(LlmError::Http {
+                status: 401,
+                retry_after: None,
+                body: "Invalid API key".to_string(),
+            }),
+        ]));
+        let mut managing_agent = ManagingAgent::new(
+            "todo app".to_string(),
+            provider,
+            Arc::new(LlmConfig::default()),
+        )
+        .await
+        .unwrap();
+        let res = managing_agent.execute_project().await;
+        assert!(matches!(res, Err(AutoGptError::Provider(_))));
+        assert!(managing_agent.factsheet.usage_summary.is_some());
+    }
 }
@@ -1,29 +1,177 @@
-use serde::{Deserialize, Serialize};
+use crate::apis::llm_provider::StructuredOutput;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Message {
     pub role: String,
     pub content: String,
 }
 
-#[derive(Debug, Serialize, Clone)]
+// Who is asking - never sent to the provider
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestMeta {
+    pub agent_position: String,
+    pub ai_function: String,
+    // Index of a self-consistency sample - identical requests for different samples must not
+    // share a cached or recorded reply (see ChatCompletion::fingerprint)
+    pub sample: u32,
+    // Schema of the decoded type when structured output was requested, so a fallback
+    // provider can ask for it in its own way (see helpers::structured_output)
+    pub output_schema: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct ChatCompletion {
     pub model: String,
     pub messages: Vec<Message>,
     pub temperature: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+    #[serde(skip)]
+    pub meta: RequestMeta,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+// Constrains the reply format - {"type": "json_object"} is JSON mode
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ResponseFormat {
+    #[serde(rename = "type")]
+    pub format_type: String,
+}
+
+impl ResponseFormat {
+    pub fn json_object() -> Self {
+        Self {
+            format_type: "json_object".to_string(),
+        }
+    }
+}
+
+// Function the model can call - parameters is a JSON schema of the arguments
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionDefinition,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+impl ChatCompletion {
+    // Stable content hash of the request - used to key recorded responses
+    // Samples after the first are keyed apart, so each gets its own reply
+    pub fn fingerprint(&self) -> String {
+        let mut serialized: String =
+            serde_json::to_string(self).expect("Failed to serialize chat completion");
+        if self.meta.sample > 0 {
+            serialized.push_str(&format!("#sample{}", self.meta.sample));
+        }
+        format!("{:x}", Sha256::digest(serialized.as_bytes()))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct APIMessage {
+    // Null when the model answers with tool calls only
+    #[serde(default, deserialize_with = "null_as_empty")]
     pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<APIToolCall>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct APIToolCall {
+    pub function: APIFunctionCall,
+}
+
+// Arguments are a JSON document encoded as a string
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct APIFunctionCall {
+    pub name: String,
+    pub arguments: String,
+}
+
+fn null_as_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct APIChoice {
     pub message: APIMessage,
+    // stop, length (cut off at max_tokens), tool_calls, content_filter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+}
+
+impl APIChoice {
+    // The reply hit max_tokens before the model finished
+    pub fn is_truncated(&self) -> bool {
+        self.finish_reason.as_deref() == Some("length")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct APIUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
 }
 
+// Server sent event chunk of a streamed completion
 #[derive(Debug, Deserialize)]
+pub struct APIStreamChunk {
+    #[serde(default)]
+    pub choices: Vec<APIStreamChoice>,
+    #[serde(default)]
+    pub usage: Option<APIUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct APIStreamChoice {
+    pub delta: APIDelta,
+    // Only set on the last chunk of a choice
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct APIDelta {
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct APIResponse {
+    // Model that produced the response, as reported by the provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
     pub choices: Vec<APIChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<APIUsage>,
+    // Structured output the reply was requested with when a fallback served it in a
+    // different way than the caller asked - None means as requested
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_output: Option<StructuredOutput>,
 }
@@ -0,0 +1,272 @@
+use crate::errors::AutoGptError;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_TEMPERATURE: f32 = 0.1;
+const DEFAULT_PROMPT_DIR: &str = "prompts";
+const CLAUDE_CONTEXT_WINDOW: usize = 200_000;
+
+// What to do when a prompt does not fit the model's context window
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    // Shorten large fenced code blocks in earlier exchanges, keeping their start and end
+    // Code in the current request is never shortened - the model would return partial code
+    TruncateCodeBlocks,
+    // Replace earlier exchanges with a summary written by the model
+    SummarizeOlderTurns,
+    // Leave out the oldest exchanges
+    DropOldest,
+}
+
+// Model tried when the ones before it fail - endpoint names an entry of LlmConfig::endpoints,
+// None means the main provider
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FallbackModel {
+    pub model: String,
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointKind {
+    #[serde(rename = "openai")]
+    OpenAi,
+    Anthropic,
+}
+
+// Additional provider endpoint - the API key is read from the api_key_env variable
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EndpointConfig {
+    pub kind: EndpointKind,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+}
+
+// Sampling settings for one request - model None means the provider default
+// send_memory sends the agent's earlier exchanges along with the request
+// context_strategies are tried in order until the prompt fits the context window
+// samples > 1 asks decoded calls for that many completions and takes the majority
+// fallbacks are tried in order when the model fails
+// max_continuations is how often a reply cut off at max_tokens is continued before failing
+// repair_attempts is how often a decoded reply that does not parse is sent back for correction
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlmSettings {
+    pub model: Option<String>,
+    pub temperature: f32,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+    pub send_memory: bool,
+    pub context_strategies: Vec<ContextStrategy>,
+    pub samples: u32,
+    pub fallbacks: Vec<FallbackModel>,
+    pub max_continuations: u32,
+    pub repair_attempts: u32,
+}
+
+impl Default for LlmSettings {
+    fn default() -> Self {
+        Self {
+            model: None,
+            temperature: DEFAULT_TEMPERATURE,
+            max_tokens: None,
+            top_p: None,
+            send_memory: true,
+            context_strategies: vec![
+                ContextStrategy::SummarizeOlderTurns,
+                ContextStrategy::DropOldest,
+                ContextStrategy::TruncateCodeBlocks,
+            ],
+            samples: 1,
+            fallbacks: vec![],
+            max_continuations: 3,
+            repair_attempts: 2,
+        }
+    }
+}
+
+// Partial settings - only the fields that are set override the level below
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct LlmSettingsOverride {
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+    pub send_memory: Option<bool>,
+    pub context_strategies: Option<Vec<ContextStrategy>>,
+    pub samples: Option<u32>,
+    pub fallbacks: Option<Vec<FallbackModel>>,
+    pub max_continuations: Option<u32>,
+    pub repair_attempts: Option<u32>,
+}
+
+impl LlmSettingsOverride {
+    fn apply(&self, settings: &mut LlmSettings) {
+        if let Some(model) = &self.model {
+            settings.model = Some(model.clone());
+        }
+        if let Some(temperature) = self.temperature {
+            settings.temperature = temperature;
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            settings.max_tokens = Some(max_tokens);
+        }
+        if let Some(top_p) = self.top_p {
+            settings.top_p = Some(top_p);
+        }
+        if let Some(send_memory) = self.send_memory {
+            settings.send_memory = send_memory;
+        }
+        if let Some(context_strategies) = &self.context_strategies {
+            settings.context_strategies = context_strategies.clone();
+        }
+        if let Some(samples) = self.samples {
+            settings.samples = samples.max(1);
+        }
+        if let Some(fallbacks) = &self.fallbacks {
+            settings.fallbacks = fallbacks.clone();
+        }
+        if let Some(max_continuations) = self.max_continuations {
+            settings.max_continuations = max_continuations;
+        }
+        if let Some(repair_attempts) = self.repair_attempts {
+            settings.repair_attempts = repair_attempts;
+        }
+    }
+}
+
+// Settings per agent position and per ai function
+// Precedence: ai function > agent position > default
+// context_windows overrides the context window size (in tokens) of a model
+// endpoints are named providers that fallback models can be served from
+// prompt_dir holds prompt template overrides (see helpers::prompt_templates)
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct LlmConfig {
+    #[serde(default)]
+    pub default: LlmSettingsOverride,
+    #[serde(default)]
+    pub agents: HashMap<String, LlmSettingsOverride>,
+    #[serde(default)]
+    pub functions: HashMap<String, LlmSettingsOverride>,
+    #[serde(default)]
+    pub context_windows: HashMap<String, usize>,
+    #[serde(default)]
+    pub endpoints: HashMap<String, EndpointConfig>,
+    #[serde(default)]
+    pub prompt_dir: Option<PathBuf>,
+}
+
+impl LlmConfig {
+    // Load the JSON file at LLM_CONFIG_PATH - unset means built in defaults everywhere
+    // {
+    //   "default": { "model": "gpt-3.5-turbo" },
+    //   "agents": { "Backend Developer": { "model": "gpt-4", "max_tokens": 4000, "fallbacks": [
+    //     { "model": "gpt-3.5-turbo" }, { "model": "llama3", "endpoint": "local" }
+    //   ] } },
+    //   "functions": { "print_project_scope": { "temperature": 0.7, "samples": 5 } },
+    //   "context_windows": { "llama3": 8192 },
+    //   "endpoints": { "local": { "kind": "openai", "base_url": "http://localhost:11434/v1" } },
+    //   "prompt_dir": "prompts"
+    // }
+    // PROMPT_TEMPLATES_DIR overrides prompt_dir
+    #[allow(dead_code)]
+    pub fn from_env() -> Result<Self, AutoGptError> {
+        dotenv().ok();
+
+        let mut config: Self = match env::var("LLM_CONFIG_PATH") {
+            Ok(path) => {
+                let contents: String = fs::read_to_string(&path).map_err(|e| {
+                    AutoGptError::Config(format!("Failed to read LLM config {}: {}", path, e))
+                })?;
+                serde_json::from_str(&contents).map_err(|e| {
+                    AutoGptError::Config(format!("Failed to decode LLM config {}: {}", path, e))
+                })?
+            }
+            Err(_) => Self::default(),
+        };
+
+        if let Some(dir) = env::var("PROMPT_TEMPLATES_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+        {
+            config.prompt_dir = Some(PathBuf::from(dir));
+        }
+
+        Ok(config)
+    }
+
+    // Directory of prompt template overrides - ./prompts unless configured
+    pub fn prompt_dir(&self) -> &Path {
+        self.prompt_dir
+            .as_deref()
+            .unwrap_or(Path::new(DEFAULT_PROMPT_DIR))
+    }
+
+    pub fn resolve(&self, agent_position: &str, ai_function: &str) -> LlmSettings {
+        let mut settings = LlmSettings::default();
+
+        self.default.apply(&mut settings);
+        if let Some(agent) = self.agents.get(agent_position) {
+            agent.apply(&mut settings);
+        }
+        if let Some(function) = self.functions.get(ai_function) {
+            function.apply(&mut settings);
+        }
+
+        settings
+    }
+
+    // Configured context window, else the known size of OpenAI and Claude models
+    // (4096 when unknown)
+    pub fn context_window(&self, model: &str) -> usize {
+        if let Some(context_window) = self.context_windows.get(model) {
+            return *context_window;
+        }
+
+        if model.starts_with("claude") {
+            CLAUDE_CONTEXT_WINDOW
+        } else {
+            tiktoken_rs::model::get_context_size(model)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tests_resolve_precedence() {
+        let config: LlmConfig = serde_json::from_str(
+            r#"{
+                "default": { "model": "gpt-3.5-turbo", "top_p": 0.9 },
+                "agents": { "Backend Developer": { "model": "gpt-4", "max_tokens": 4000 } },
+                "functions": { "print_fixed_code": { "temperature": 0.0 } }
+            }"#,
+        )
+        .unwrap();
+
+        let scope = config.resolve("Solutions Architect", "print_project_scope");
+        assert_eq!(scope.model.as_deref(), Some("gpt-3.5-turbo"));
+        assert_eq!(scope.temperature, 0.1);
+        assert_eq!(scope.top_p, Some(0.9));
+
+        let fix = config.resolve("Backend Developer", "print_fixed_code");
+        assert_eq!(fix.model.as_deref(), Some("gpt-4"));
+        assert_eq!(fix.max_tokens, Some(4000));
+        assert_eq!(fix.temperature, 0.0);
+
+        assert_eq!(
+            LlmConfig::default().resolve("Project Manager", "convert_user_input_to_goal"),
+            LlmSettings::default()
+        );
+    }
+}
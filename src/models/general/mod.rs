@@ -1 +1,4 @@
 pub mod llm;
+pub mod llm_config;
+pub mod self_consistency;
+pub mod usage;
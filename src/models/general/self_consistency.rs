@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+// Agreement between sampled completions - 1.0 means every sample gave the same answer
+// Failed samples count as disagreeing
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Confidence {
+    pub samples: usize,
+    // Lowest agreement of any field
+    pub overall: f32,
+    // Agreement per object field - empty when the output is not an object
+    pub fields: BTreeMap<String, f32>,
+}
+
+// Majority answer of the decoded samples - objects are voted field by field,
+// anything else as a whole. Ties go to the earliest sample.
+pub fn majority_vote(decoded: &[Value], samples: usize) -> Option<(Value, Confidence)> {
+    if decoded.is_empty() {
+        return None;
+    }
+    let samples: usize = samples.max(decoded.len());
+
+    let objects: Option<Vec<&serde_json::Map<String, Value>>> =
+        decoded.iter().map(Value::as_object).collect();
+
+    let Some(objects) = objects else {
+        let (winner, votes) = most_common(decoded.iter());
+        let agreement: f32 = votes as f32 / samples as f32;
+        return Some((
+            winner.clone(),
+            Confidence {
+                samples,
+                overall: agreement,
+                fields: BTreeMap::new(),
+            },
+        ));
+    };
+
+    let mut voted = serde_json::Map::new();
+    let mut fields: BTreeMap<String, f32> = BTreeMap::new();
+
+    // Every field any sample has
+    let keys: BTreeSet<&String> = objects.iter().flat_map(|object| object.keys()).collect();
+
+    for key in keys {
+        let (winner, votes) = most_common(objects.iter().filter_map(|object| object.get(key)));
+        voted.insert(key.clone(), winner.clone());
+        fields.insert(key.clone(), votes as f32 / samples as f32);
+    }
+
+    let overall: f32 = fields.values().copied().fold(1.0, f32::min);
+    Some((
+        Value::Object(voted),
+        Confidence {
+            samples,
+            overall,
+            fields,
+        },
+    ))
+}
+
+fn most_common<'a>(values: impl Iterator<Item = &'a Value>) -> (&'a Value, usize) {
+    let mut counts: Vec<(&Value, usize)> = vec![];
+    for value in values {
+        match counts.iter_mut().find(|(seen, _)| *seen == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+
+    // max_by_key keeps the last maximum - reverse so ties go to the earliest value
+    counts
+        .into_iter()
+        .rev()
+        .max_by_key(|(_, count)| *count)
+        .unwrap_or((&Value::Null, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn tests_majority_per_field() {
+        let decoded = vec![
+            json!({"is_crud_required": true, "is_external_urls_required": true}),
+            json!({"is_crud_required": true, "is_external_urls_required": false}),
+            json!({"is_crud_required": true, "is_external_urls_required": true}),
+        ];
+
+        // Four samples requested, one failed to decode
+        let (voted, confidence) = majority_vote(&decoded, 4).unwrap();
+        assert_eq!(
+            voted,
+            json!({"is_crud_required": true, "is_external_urls_required": true})
+        );
+        assert_eq!(confidence.samples, 4);
+        assert_eq!(confidence.fields["is_crud_required"], 0.75);
+        assert_eq!(confidence.fields["is_external_urls_required"], 0.5);
+        assert_eq!(confidence.overall, 0.5);
+
+        // Whole value vote for non objects, ties go to the earliest sample
+        let (voted, confidence) = majority_vote(&[json!(["a"]), json!(["b"])], 2).unwrap();
+        assert_eq!(voted, json!(["a"]));
+        assert_eq!(confidence.overall, 0.5);
+        assert!(majority_vote(&[], 3).is_none());
+    }
+}
@@ -0,0 +1,253 @@
+use crate::errors::AutoGptError;
+use crate::models::general::llm::{APIUsage, RequestMeta};
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::sync::Mutex;
+
+// Price in USD per 1K tokens
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub prompt_per_1k: f64,
+    pub completion_per_1k: f64,
+}
+
+// Prices per model name
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceTable {
+    prices: BTreeMap<String, ModelPrice>,
+}
+
+impl Default for PriceTable {
+    fn default() -> Self {
+        let price = |prompt_per_1k: f64, completion_per_1k: f64| ModelPrice {
+            prompt_per_1k,
+            completion_per_1k,
+        };
+
+        Self::new(BTreeMap::from([
+            ("gpt-3.5-turbo".to_string(), price(0.0005, 0.0015)),
+            ("gpt-3.5-turbo-16k".to_string(), price(0.003, 0.004)),
+            ("gpt-4".to_string(), price(0.03, 0.06)),
+            ("gpt-4-32k".to_string(), price(0.06, 0.12)),
+            ("gpt-4-turbo".to_string(), price(0.01, 0.03)),
+            ("gpt-4o".to_string(), price(0.0025, 0.01)),
+            ("gpt-4o-mini".to_string(), price(0.00015, 0.0006)),
+            ("gpt-4.1".to_string(), price(0.002, 0.008)),
+            ("gpt-4.1-mini".to_string(), price(0.0004, 0.0016)),
+            ("gpt-4.1-nano".to_string(), price(0.0001, 0.0004)),
+            ("o1".to_string(), price(0.015, 0.06)),
+            ("o3-mini".to_string(), price(0.0011, 0.0044)),
+            ("claude-3-5-haiku".to_string(), price(0.0008, 0.004)),
+            ("claude-3-5-haiku-latest".to_string(), price(0.0008, 0.004)),
+            ("claude-3-5-sonnet".to_string(), price(0.003, 0.015)),
+            ("claude-3-5-sonnet-latest".to_string(), price(0.003, 0.015)),
+            ("claude-3-7-sonnet".to_string(), price(0.003, 0.015)),
+            ("claude-3-7-sonnet-latest".to_string(), price(0.003, 0.015)),
+            ("claude-3-opus".to_string(), price(0.015, 0.075)),
+            ("claude-3-opus-latest".to_string(), price(0.015, 0.075)),
+            ("claude-sonnet-4".to_string(), price(0.003, 0.015)),
+            ("claude-opus-4".to_string(), price(0.015, 0.075)),
+        ]))
+    }
+}
+
+impl PriceTable {
+    pub fn new(prices: BTreeMap<String, ModelPrice>) -> Self {
+        Self { prices }
+    }
+
+    // Default prices, overridden by the JSON file at LLM_PRICE_TABLE_PATH
+    // File format: { "model-name": { "prompt_per_1k": 0.001, "completion_per_1k": 0.002 } }
+    pub fn from_env() -> Result<Self, AutoGptError> {
+        dotenv().ok();
+
+        let mut table = Self::default();
+
+        if let Ok(path) = env::var("LLM_PRICE_TABLE_PATH") {
+            let contents: String = fs::read_to_string(&path).map_err(|e| {
+                AutoGptError::Config(format!("Failed to read price table {}: {}", path, e))
+            })?;
+            let overrides: BTreeMap<String, ModelPrice> =
+                serde_json::from_str(&contents).map_err(|e| {
+                    AutoGptError::Config(format!("Failed to decode price table {}: {}", path, e))
+                })?;
+            table.prices.extend(overrides);
+        }
+
+        Ok(table)
+    }
+
+    // Exact match, or a dated snapshot of a known model (gpt-4o-2024-08-06 is priced as gpt-4o,
+    // claude-3-5-sonnet-20241022 as claude-3-5-sonnet) - a plain prefix would price gpt-4o as gpt-4
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.prices
+            .get(model)
+            .or_else(|| self.prices.get(strip_date_suffix(model)?))
+            .copied()
+    }
+}
+
+// Model name without a trailing -YYYY-MM-DD or -YYYYMMDD snapshot date
+fn strip_date_suffix(model: &str) -> Option<&str> {
+    let is_date = |date: &str| {
+        let digits: String = date.replace('-', "");
+        digits.len() == 8
+            && digits.chars().all(|c| c.is_ascii_digit())
+            && (date.len() == 8 || date.split('-').map(str::len).eq([4, 2, 2]))
+    };
+
+    [11, 9]
+        .into_iter()
+        .filter(|len| model.len() > *len)
+        .map(|len| model.split_at(model.len() - len))
+        .find(|(_, suffix)| suffix.starts_with('-') && is_date(&suffix[1..]))
+        .map(|(name, _)| name)
+}
+
+// Running totals for one bucket
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UsageTotals {
+    pub calls: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, usage: &APIUsage, cost_usd: f64) {
+        self.calls += 1;
+        self.prompt_tokens += u64::from(usage.prompt_tokens);
+        self.completion_tokens += u64::from(usage.completion_tokens);
+        self.cost_usd += cost_usd;
+    }
+}
+
+// Token usage and cost of a project run
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UsageSummary {
+    pub project: UsageTotals,
+    pub by_agent: BTreeMap<String, UsageTotals>,
+    pub by_function: BTreeMap<String, UsageTotals>,
+    pub unpriced_models: Vec<String>,
+}
+
+// Collects usage of every LLM call made during a run
+#[derive(Debug)]
+pub struct UsageLedger {
+    prices: PriceTable,
+    summary: Mutex<UsageSummary>,
+}
+
+impl UsageLedger {
+    pub fn new(prices: PriceTable) -> Self {
+        Self {
+            prices,
+            summary: Mutex::new(UsageSummary::default()),
+        }
+    }
+
+    pub fn record(&self, meta: &RequestMeta, model: &str, usage: &APIUsage) {
+        let mut summary = self.summary.lock().unwrap();
+
+        let cost_usd: f64 = match self.prices.price(model) {
+            Some(price) => {
+                f64::from(usage.prompt_tokens) / 1000.0 * price.prompt_per_1k
+                    + f64::from(usage.completion_tokens) / 1000.0 * price.completion_per_1k
+            }
+            None => {
+                if !summary.unpriced_models.iter().any(|m| m == model) {
+                    summary.unpriced_models.push(model.to_string());
+                }
+                0.0
+            }
+        };
+
+        summary.project.add(usage, cost_usd);
+        summary
+            .by_agent
+            .entry(meta.agent_position.clone())
+            .or_default()
+            .add(usage, cost_usd);
+        summary
+            .by_function
+            .entry(meta.ai_function.clone())
+            .or_default()
+            .add(usage, cost_usd);
+    }
+
+    pub fn summary(&self) -> UsageSummary {
+        self.summary.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(agent_position: &str, ai_function: &str) -> RequestMeta {
+        RequestMeta {
+            agent_position: agent_position.to_string(),
+            ai_function: ai_function.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn usage(prompt_tokens: u32, completion_tokens: u32) -> APIUsage {
+        APIUsage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+
+    #[test]
+    fn tests_price_lookup_by_snapshot_date() {
+        let prices = PriceTable::default();
+        assert_eq!(prices.price("gpt-4o-2024-08-06"), prices.price("gpt-4o"));
+        assert_eq!(
+            prices.price("claude-3-5-sonnet-20241022"),
+            prices.price("claude-3-5-sonnet")
+        );
+
+        // Newer models are not billed at the rates of a shorter name
+        let gpt_4o_mini = prices.price("gpt-4o-mini").unwrap();
+        assert_ne!(Some(gpt_4o_mini), prices.price("gpt-4"));
+        assert_ne!(Some(gpt_4o_mini), prices.price("gpt-4o"));
+        assert_eq!(prices.price("gpt-4o-mini-2024-07-18"), Some(gpt_4o_mini));
+        assert_eq!(prices.price("gpt-4-0613"), None);
+        assert_eq!(prices.price("llama-3-8b"), None);
+    }
+
+    #[test]
+    fn tests_ledger_totals() {
+        let ledger = UsageLedger::new(PriceTable::default());
+
+        ledger.record(
+            &meta("Project Manager", "convert_user_input_to_goal"),
+            "gpt-4",
+            &usage(1000, 500),
+        );
+        ledger.record(
+            &meta("Solutions Architect", "print_project_scope"),
+            "gpt-4",
+            &usage(2000, 0),
+        );
+        ledger.record(
+            &meta("Solutions Architect", "print_site_urls"),
+            "local-model",
+            &usage(10, 10),
+        );
+
+        let summary = ledger.summary();
+        assert_eq!(summary.project.calls, 3);
+        assert_eq!(summary.project.prompt_tokens, 3010);
+        assert!((summary.project.cost_usd - 0.12).abs() < 1e-9);
+        assert_eq!(summary.by_agent["Solutions Architect"].calls, 2);
+        assert!((summary.by_function["print_project_scope"].cost_usd - 0.06).abs() < 1e-9);
+        assert_eq!(summary.unpriced_models, vec!["local-model"]);
+    }
+}